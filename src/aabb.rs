@@ -1,4 +1,5 @@
 use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
@@ -11,4 +12,45 @@ impl Aabb {
     pub fn new(min: [f32; 4], max: [f32; 4]) -> Self {
         Self { min, max }
     }
+
+    pub fn from_min_max(min: Vec3, max: Vec3) -> Self {
+        Self::new([min.x, min.y, min.z, 0f32], [max.x, max.y, max.z, 0f32])
+    }
+
+    pub fn min(&self) -> Vec3 {
+        Vec3::new(self.min[0], self.min[1], self.min[2])
+    }
+
+    pub fn max(&self) -> Vec3 {
+        Vec3::new(self.max[0], self.max[1], self.max[2])
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min() + self.max()) * 0.5f32
+    }
+
+    pub fn half_extent(&self) -> Vec3 {
+        (self.max() - self.min()) * 0.5f32
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb::from_min_max(self.min().min(other.min()), self.max().max(other.max()))
+    }
+
+    /// Bounds of the box after an affine transform.
+    pub fn transformed(&self, transform: &Mat4) -> Aabb {
+        let mut min = Vec3::splat(f32::MAX);
+        let mut max = Vec3::splat(f32::MIN);
+        for corner in 0..8 {
+            let point = Vec3::select(
+                glam::BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0),
+                self.max(),
+                self.min(),
+            );
+            let point = transform.transform_point3(point);
+            min = min.min(point);
+            max = max.max(point);
+        }
+        Aabb::from_min_max(min, max)
+    }
 }
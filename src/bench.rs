@@ -0,0 +1,303 @@
+use crate::cli::Args;
+use crate::editor::view::View;
+use crate::headless::{load_scene, request_device};
+use crate::profiler::{GpuFrame, GpuProfiler, TimedPass};
+use crate::renderer::{max_weights_bytes, VolumeRenderer};
+use crate::tone_mapping::ToneMapper;
+use crate::SCREEN_SIZE;
+use eframe::wgpu;
+use std::f32::consts::{FRAC_PI_2, PI};
+use std::time::Instant;
+
+/// Untimed frames before every case, the first dispatches of a pipeline run slower.
+const WARMUP_FRAMES: u32 = 3;
+const STEP_SIZES: [f32; 3] = [1.5f32, 3f32, 6f32];
+/// Name, angle around the volumes and distance relative to the framing view.
+const VIEWS: [(&str, f32, f32); 4] = [
+    ("front", 0f32, 1f32),
+    ("side", FRAC_PI_2, 1f32),
+    ("back", PI, 1f32),
+    ("close", 0f32, 0.5f32),
+];
+
+/// Distribution of the times of one case in milliseconds.
+struct Percentiles {
+    mean: f32,
+    min: f32,
+    p50: f32,
+    p90: f32,
+    p99: f32,
+    max: f32,
+}
+
+impl Percentiles {
+    const NAMES: [&'static str; 6] = ["mean", "min", "p50", "p90", "p99", "max"];
+
+    fn of(samples: &[f32]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f32::total_cmp);
+        // nearest rank
+        let percentile = |p: f32| {
+            let rank = (p / 100f32 * sorted.len() as f32).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+        Some(Self {
+            mean: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            min: sorted[0],
+            p50: percentile(50f32),
+            p90: percentile(90f32),
+            p99: percentile(99f32),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+
+    fn values(&self) -> [f32; 6] {
+        [self.mean, self.min, self.p50, self.p90, self.p99, self.max]
+    }
+}
+
+/// One view rendered with one step size.
+struct Case {
+    view: &'static str,
+    step: f32,
+    compute_ms: Option<Percentiles>,
+    blit_ms: Option<Percentiles>,
+    /// Wall time from submitting a frame until its timings are read back, measured
+    /// on the CPU so that adapters without timestamp queries report something too.
+    frame_ms: Percentiles,
+    samples_per_ray: f32,
+    early_terminated_percent: f32,
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn json_percentiles(percentiles: Option<&Percentiles>) -> String {
+    let Some(percentiles) = percentiles else {
+        return "null".to_string();
+    };
+    let fields: Vec<String> = Percentiles::NAMES
+        .iter()
+        .zip(percentiles.values())
+        .map(|(name, value)| format!("\"{}\": {:.4}", name, value))
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn json_report(args: &Args, info: &wgpu::AdapterInfo, timestamps: bool, cases: &[Case]) -> String {
+    let adapter = [
+        format!("\"name\": {}", json_string(&info.name)),
+        format!("\"vendor\": {}", info.vendor),
+        format!("\"device\": {}", info.device),
+        format!("\"device_type\": \"{:?}\"", info.device_type),
+        format!("\"backend\": \"{:?}\"", info.backend),
+        format!("\"driver\": {}", json_string(&info.driver)),
+        format!("\"driver_info\": {}", json_string(&info.driver_info)),
+    ];
+    let inputs: Vec<String> = args.inputs.iter().map(|input| json_string(input)).collect();
+    let cases: Vec<String> = cases
+        .iter()
+        .map(|case| {
+            let fields = [
+                format!("\"view\": {}", json_string(case.view)),
+                format!("\"step\": {}", case.step),
+                format!(
+                    "\"compute_ms\": {}",
+                    json_percentiles(case.compute_ms.as_ref())
+                ),
+                format!("\"blit_ms\": {}", json_percentiles(case.blit_ms.as_ref())),
+                format!("\"frame_ms\": {}", json_percentiles(Some(&case.frame_ms))),
+                format!("\"samples_per_ray\": {:.2}", case.samples_per_ray),
+                format!(
+                    "\"early_terminated_percent\": {:.2}",
+                    case.early_terminated_percent
+                ),
+            ];
+            format!("    {{{}}}", fields.join(", "))
+        })
+        .collect();
+
+    let lines = [
+        "{".to_string(),
+        format!("  \"adapter\": {{{}}},", adapter.join(", ")),
+        format!("  \"timestamps\": {},", timestamps),
+        format!("  \"adaptive_step\": {},", args.adaptive),
+        format!("  \"light_cache\": {},", args.light_cache),
+        format!("  \"inputs\": [{}],", inputs.join(", ")),
+        format!("  \"width\": {},", SCREEN_SIZE[0]),
+        format!("  \"height\": {},", SCREEN_SIZE[1]),
+        format!("  \"frames\": {},", args.bench_frames),
+        format!("  \"cases\": [\n{}\n  ]", cases.join(",\n")),
+        "}".to_string(),
+    ];
+    lines.join("\n") + "\n"
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// One row per case, the adapter is repeated on every row so that reports of several
+/// machines can be concatenated.
+fn csv_report(info: &wgpu::AdapterInfo, cases: &[Case]) -> String {
+    let mut header = vec!["view".to_string(), "step".to_string()];
+    for series in ["compute", "blit", "frame"] {
+        for name in Percentiles::NAMES {
+            header.push(format!("{}_{}_ms", series, name));
+        }
+    }
+    header.extend(
+        [
+            "samples_per_ray",
+            "early_terminated_percent",
+            "adapter",
+            "backend",
+            "driver",
+        ]
+        .map(str::to_string),
+    );
+
+    let mut lines = vec![header.join(",")];
+    for case in cases {
+        let mut row = vec![case.view.to_string(), case.step.to_string()];
+        for percentiles in [&case.compute_ms, &case.blit_ms] {
+            match percentiles {
+                Some(percentiles) => {
+                    row.extend(percentiles.values().map(|value| format!("{:.4}", value)))
+                }
+                None => row.extend(std::iter::repeat_n(String::new(), Percentiles::NAMES.len())),
+            }
+        }
+        row.extend(case.frame_ms.values().map(|value| format!("{:.4}", value)));
+        row.push(format!("{:.2}", case.samples_per_ray));
+        row.push(format!("{:.2}", case.early_terminated_percent));
+        row.push(csv_field(&info.name));
+        row.push(format!("{:?}", info.backend));
+        row.push(csv_field(&info.driver));
+        lines.push(row.join(","));
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Renders every view with every step size for `--bench-frames` frames each and saves
+/// the times as JSON or CSV.
+pub fn run(args: &Args) -> Result<(), String> {
+    let Some(report) = &args.bench else {
+        return Err("no report path given".to_string());
+    };
+    if args.inputs.is_empty() {
+        return Err("no VDB file to benchmark".to_string());
+    }
+
+    let mut settings = load_scene(args)?;
+    let (adapter, device, queue) = request_device(args.software)?;
+    settings.weights_limit = max_weights_bytes(&device.limits());
+    settings.check_weights_size(&settings.load_options, 0)?;
+    let info = adapter.get_info();
+
+    let mut renderer = VolumeRenderer::new(&device, SCREEN_SIZE[0], SCREEN_SIZE[1]);
+    let output_format = wgpu::TextureFormat::Rgba8Unorm;
+    let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Bench output texture"),
+        size: renderer.result_texture.size(),
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: output_format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let tone_mapper = ToneMapper::new(&device, &renderer, output_format, true);
+    let mut profiler = GpuProfiler::new(&device, &queue);
+
+    let bbox = settings.scene_bbox();
+    let framing = View::framing(&bbox);
+    let mut cases = Vec::new();
+    for (name, angle, distance) in VIEWS {
+        let mut view = framing.orbit(bbox.center(), angle);
+        view.translation = bbox.center() + (view.translation - bbox.center()) * distance;
+        settings.matrix = view.matrix();
+        for step in STEP_SIZES {
+            settings.ray_marching_step = step;
+            renderer.prepare(&device, &queue, &settings);
+            tone_mapper.prepare(&queue, &settings);
+
+            let mut frames: Vec<GpuFrame> = Vec::new();
+            let mut frame_ms = Vec::new();
+            for i in 0..WARMUP_FRAMES + args.bench_frames {
+                let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Bench encoder"),
+                });
+                renderer.dispatch(&mut encoder, profiler.compute_timestamp_writes());
+                tone_mapper.render(
+                    &mut encoder,
+                    &output_view,
+                    profiler.render_timestamp_writes(TimedPass::Blit),
+                );
+                let start = Instant::now();
+                queue.submit(Some(encoder.finish()));
+                let frame = profiler.read_blocking(&device, &queue, &renderer.ray_stats_buffer);
+                if i >= WARMUP_FRAMES {
+                    frame_ms.push(start.elapsed().as_secs_f32() * 1000f32);
+                    frames.push(frame);
+                }
+            }
+
+            let pass_ms = |pass: TimedPass| -> Vec<f32> {
+                frames
+                    .iter()
+                    .filter_map(|frame| frame.pass_ms(pass))
+                    .collect()
+            };
+            // every frame renders the same image, so the ray stats are the same too
+            let stats = frames.last().map(|frame| frame.stats).unwrap_or_default();
+            let case = Case {
+                view: name,
+                step,
+                compute_ms: Percentiles::of(&pass_ms(TimedPass::Compute)),
+                blit_ms: Percentiles::of(&pass_ms(TimedPass::Blit)),
+                frame_ms: Percentiles::of(&frame_ms).ok_or("no frames were timed")?,
+                samples_per_ray: stats.samples_per_ray(),
+                early_terminated_percent: stats.early_terminated_percent(),
+            };
+            log::info!(
+                "{} view, step {}: compute p50 {}, frame p50 {:.2} ms",
+                name,
+                step,
+                case.compute_ms
+                    .as_ref()
+                    .map_or("-".to_string(), |ms| format!("{:.2} ms", ms.p50)),
+                case.frame_ms.p50
+            );
+            cases.push(case);
+        }
+    }
+
+    let text = if report.to_lowercase().ends_with(".csv") {
+        csv_report(&info, &cases)
+    } else {
+        json_report(args, &info, profiler.has_timestamps(), &cases)
+    };
+    std::fs::write(report, text).map_err(|err| format!("Failed to write {}: {}", report, err))?;
+    log::info!("Saved {}", report);
+    Ok(())
+}
@@ -0,0 +1,158 @@
+const USAGE: &str = "\
+usage: render [options] <file.vdb>...
+
+options:
+    --output <path>        render without a window and save PNG images, or EXR
+                           for a .exr extension; '#' characters are replaced
+                           by the frame number
+    --transparent          leave the background out, the alpha channel holds
+                           the coverage of the volumes
+    --adaptive             adapt the step size to the density and distance
+    --light-cache          light from a precomputed transmittance volume
+    --frames <start>-<end> frame range of VDB sequences to render
+    --turntable <frames>   orbit the camera once around the volumes
+    --camera <file>        follow a camera path saved from the editor
+    --bookmark <name>      render from a camera bookmarked in the editor
+    --bench <report>       time a fixed set of views and step sizes and save
+                           the GPU times as JSON, or CSV for a .csv extension
+    --bench-frames <n>     frames rendered per view and step size (default 30)
+    --software             render on a software adapter";
+
+/// Frames timed per bench case when `--bench-frames` isn't given.
+const DEFAULT_BENCH_FRAMES: u32 = 30;
+
+pub struct Args {
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    pub frames: Option<(u32, u32)>,
+    pub transparent: bool,
+    pub adaptive: bool,
+    pub light_cache: bool,
+    pub turntable: Option<u32>,
+    pub camera_path: Option<String>,
+    pub bookmark: Option<String>,
+    /// Report file of the benchmark, runs it instead of rendering.
+    pub bench: Option<String>,
+    pub bench_frames: u32,
+    pub software: bool,
+}
+
+fn parse_range(value: &str) -> Option<(u32, u32)> {
+    let (start, end) = value.split_once('-')?;
+    let start = start.trim().parse().ok()?;
+    let end = end.trim().parse().ok()?;
+    (start <= end).then_some((start, end))
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    fn parse_from(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut result = Self {
+            inputs: Vec::new(),
+            output: None,
+            frames: None,
+            transparent: false,
+            adaptive: false,
+            light_cache: false,
+            turntable: None,
+            camera_path: None,
+            bookmark: None,
+            bench: None,
+            bench_frames: DEFAULT_BENCH_FRAMES,
+            software: false,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--output" => {
+                    result.output = Some(args.next().ok_or(USAGE)?);
+                }
+                "--frames" => {
+                    let value = args.next().ok_or(USAGE)?;
+                    result.frames =
+                        Some(parse_range(&value).ok_or(format!("invalid frame range {}", value))?);
+                }
+                "--transparent" => result.transparent = true,
+                "--adaptive" => result.adaptive = true,
+                "--light-cache" => result.light_cache = true,
+                "--turntable" => {
+                    let value = args.next().ok_or(USAGE)?;
+                    result.turntable = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&frames| frames > 0)
+                            .ok_or(format!("invalid frame count {}", value))?,
+                    );
+                }
+                "--camera" => {
+                    result.camera_path = Some(args.next().ok_or(USAGE)?);
+                }
+                "--bookmark" => {
+                    result.bookmark = Some(args.next().ok_or(USAGE)?);
+                }
+                "--bench" => {
+                    result.bench = Some(args.next().ok_or(USAGE)?);
+                }
+                "--bench-frames" => {
+                    let value = args.next().ok_or(USAGE)?;
+                    result.bench_frames = value
+                        .parse()
+                        .ok()
+                        .filter(|&frames| frames > 0)
+                        .ok_or(format!("invalid frame count {}", value))?;
+                }
+                "--software" => result.software = true,
+                "--help" | "-h" => return Err(USAGE.to_string()),
+                _ if arg.starts_with("--") => {
+                    return Err(format!("unknown option {}\n{}", arg, USAGE));
+                }
+                _ => result.inputs.push(arg),
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.output.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse_from(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range("1-120"), Some((1, 120)));
+        assert_eq!(parse_range(" 5 - 5 "), Some((5, 5)));
+        assert_eq!(parse_range("0-0"), Some((0, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        assert_eq!(parse_range("10-1"), None);
+        assert_eq!(parse_range("10"), None);
+        assert_eq!(parse_range("-5"), None);
+        assert_eq!(parse_range("1-"), None);
+        assert_eq!(parse_range("a-b"), None);
+        assert_eq!(parse_range("1-2-3"), None);
+    }
+
+    #[test]
+    fn frames_option() {
+        let args = parse(&["--frames", "3-7", "smoke.0001.vdb"]).unwrap();
+        assert_eq!(args.frames, Some((3, 7)));
+        assert_eq!(args.inputs, vec!["smoke.0001.vdb".to_string()]);
+        assert!(parse(&["--frames", "7-3", "smoke.0001.vdb"]).is_err());
+        assert!(parse(&["--frames"]).is_err());
+    }
+}
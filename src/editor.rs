@@ -1,49 +1,143 @@
+mod bookmarks;
+mod camera_animation;
+pub mod camera_path;
+mod clip_gizmo;
 mod fps_controller;
+mod grid_info;
+mod history;
+mod image_export;
+mod load_progress;
+mod outliner;
+mod performance;
 mod render_view;
-mod settings;
+pub mod settings;
+mod slice_view;
+mod timeline;
 mod tree_behaviour;
-mod view;
+mod velocity_overlay;
+pub mod view;
 
 use std::{
     collections::HashSet,
     sync::{Arc, Mutex},
 };
 
-use egui::Key;
+use egui::{Key, KeyboardShortcut, Modifiers};
 use fps_controller::FPSController;
-use glam::{Mat4, Quat, Vec3};
+use glam::{Quat, Vec3};
+use history::History;
+use image_export::{ExportOptions, ImageExport};
 use render_view::RenderView;
 use settings::Settings;
 use tree_behaviour::TreeBehavior;
-use view::View;
+use view::{Transition, View};
 
+use crate::aabb::Aabb;
+use crate::sequence::SequenceLoader;
 use crate::{CAMERA_MOVE_SPEED, CAMERA_ROTATION_SPEED};
 
+/// Sequence frames loaded ahead of the playhead.
+const PREFETCH_FRAMES: u32 = 3;
+const BOOKMARK_KEYS: [Key; bookmarks::MAX_KEYED_BOOKMARKS] = [
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+];
+const SAVE_IMAGE_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::S);
+const UNDO_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+const REDO_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Y);
+const REDO_SHIFT_SHORTCUT: KeyboardShortcut =
+    KeyboardShortcut::new(Modifiers::COMMAND.plus(Modifiers::SHIFT), Key::Z);
+
 pub struct Editor {
     _viewport: Option<RenderView>,
     tree: egui_tiles::Tree<tree_behaviour::Pane>,
     settings: Arc<Mutex<Settings>>,
     camera_to_world: View,
+    /// Scales camera movement to the size of the loaded volume.
+    move_scale: f32,
+    sequence_loader: SequenceLoader,
+    /// Last camera placed by the camera path, the user can move away from it while paused.
+    previewed_view: Option<View>,
+    /// Flight to a recalled bookmark.
+    transition: Option<Transition>,
+    history: History,
+    /// Device and queue of the window, used to render images for saving.
+    render_state: Option<egui_wgpu::RenderState>,
+    save_dialog_open: bool,
+    export_options: ExportOptions,
+    export: Option<ImageExport>,
+    export_error: Option<String>,
 }
 
 impl Editor {
-    pub fn new(_cc: &eframe::CreationContext<'_>, width: u32, height: u32) -> Self {
+    pub fn new(
+        _cc: &eframe::CreationContext<'_>,
+        width: u32,
+        height: u32,
+        inputs: &[String],
+    ) -> Self {
         catppuccin_egui::set_theme(&_cc.egui_ctx, catppuccin_egui::MOCHA);
 
-        let settings = Settings::default();
+        let mut settings = Settings::default();
+        for filename in inputs {
+            settings.open_volume(filename);
+        }
 
         let settings = Arc::new(Mutex::new(settings));
 
         let tree = TreeBehavior::create_tree(settings.clone());
-        Self {
-            _viewport: RenderView::new(_cc, width, height, settings.clone()),
+        let viewport = RenderView::new(_cc, width, height, settings.clone());
+
+        let editor = Self {
+            _viewport: viewport,
             tree,
             settings: settings.clone(),
             camera_to_world: View::default(),
+            move_scale: 1f32,
+            sequence_loader: SequenceLoader::new(),
+            previewed_view: None,
+            transition: None,
+            history: History::default(),
+            render_state: _cc.wgpu_render_state.clone(),
+            save_dialog_open: false,
+            export_options: ExportOptions::default(),
+            export: None,
+            export_error: None,
+        };
+        editor.send_camera_matrix();
+        editor
+    }
+
+    /// Points the camera at the whole scene.
+    fn frame_scene(&mut self, bbox: Aabb) {
+        let radius = bbox.half_extent().length();
+        self.camera_to_world = View::framing(&bbox);
+        self.move_scale = if radius > 0f32 { radius / 100f32 } else { 1f32 };
+        self.send_camera_matrix();
+    }
+
+    /// Moves finished background loads into the scene, framing the first one.
+    fn poll_loads(&mut self) {
+        let Ok(mut settings) = self.settings.lock() else {
+            return;
+        };
+        settings.poll_repack();
+        let was_empty = settings.volumes.is_empty();
+        if settings.poll_loads() && was_empty {
+            let bbox = settings.scene_bbox();
+            drop(settings);
+            self.frame_scene(bbox);
         }
     }
 
-    fn handle_key_down(&mut self, keys: HashSet<Key>) {
+    fn handle_key_down(&mut self, keys: &HashSet<Key>) {
         let rotation = self.camera_to_world.rotation_y * self.camera_to_world.rotation_x;
 
         let view_dir = (rotation * Vec3::Z).normalize();
@@ -59,7 +153,8 @@ impl Editor {
                 Key::Q => -up_dir,
                 Key::E => up_dir,
                 _ => Vec3::ZERO,
-            } * CAMERA_MOVE_SPEED;
+            } * CAMERA_MOVE_SPEED
+                * self.move_scale;
 
             self.camera_to_world.rotation_x *= match key {
                 Key::ArrowUp => Quat::from_rotation_x(-0.01f32 * CAMERA_ROTATION_SPEED),
@@ -83,19 +178,278 @@ impl Editor {
     }
 
     fn send_camera_matrix(&self) {
-        let rotation = self.camera_to_world.rotation_y * self.camera_to_world.rotation_x;
         if let Ok(mut settings) = self.settings.lock() {
-            settings.matrix =
-                Mat4::from_rotation_translation(rotation, self.camera_to_world.translation)
+            settings.matrix = self.camera_to_world.matrix();
+        }
+    }
+
+    /// Moves the camera along the camera path while previewing it.
+    fn follow_camera_path(&mut self) {
+        let Ok(settings) = self.settings.lock() else {
+            return;
+        };
+        let view = if settings.camera_path.preview {
+            settings.camera_path.view_at(settings.timeline.frame as f32)
+        } else {
+            None
+        };
+        drop(settings);
+        if let Some(view) = view {
+            if self.previewed_view != Some(view) {
+                self.camera_to_world = view;
+                self.send_camera_matrix();
+            }
+        }
+        self.previewed_view = view;
+    }
+
+    /// Flies the camera to bookmarks recalled from the list or with the number keys.
+    fn update_bookmarks(&mut self, input: &egui::InputState, typing: bool) {
+        let Ok(mut settings) = self.settings.lock() else {
+            return;
+        };
+        if !typing {
+            for (index, &key) in BOOKMARK_KEYS.iter().enumerate() {
+                if input.key_pressed(key) {
+                    settings.bookmarks.recall(index);
+                }
+            }
+        }
+        let recalled = settings.bookmarks.take_recalled();
+        drop(settings);
+
+        if let Some(view) = recalled {
+            self.transition = Some(Transition::new(self.camera_to_world, view));
+        }
+        let Some(transition) = &mut self.transition else {
+            return;
+        };
+        self.camera_to_world = transition.advance(input.stable_dt);
+        if transition.is_finished() {
+            self.transition = None;
+        }
+        self.send_camera_matrix();
+    }
+
+    /// Something on screen changes without input: the camera is flying or held moving,
+    /// playback runs, or files and images are being read or written in the background.
+    fn is_animating(&self, input: &egui::InputState) -> bool {
+        if !input.keys_down.is_empty()
+            || self.transition.is_some()
+            || self.export.is_some()
+            || self.sequence_loader.is_loading()
+        {
+            return true;
+        }
+        self.settings.lock().is_ok_and(|settings| {
+            !settings.loads.is_empty()
+                || settings.repack.is_some()
+                || settings.performance.pending
+                || (settings.timeline.playing && settings.is_animated())
+        })
+    }
+
+    /// Records settings edits and applies the undo and redo shortcuts.
+    fn update_history(&mut self, ctx: &egui::Context) {
+        let pointer_down = ctx.input(|i| i.pointer.any_down());
+        let Ok(mut settings) = self.settings.lock() else {
+            return;
+        };
+        self.history.record(&settings, pointer_down);
+        // text fields have their own undo
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        // checked first, Ctrl+Z would match it as well
+        let redo = ctx.input_mut(|i| {
+            i.consume_shortcut(&REDO_SHIFT_SHORTCUT) || i.consume_shortcut(&REDO_SHORTCUT)
+        });
+        if redo {
+            self.history.redo(&mut settings);
+        } else if ctx.input_mut(|i| i.consume_shortcut(&UNDO_SHORTCUT)) {
+            self.history.undo(&mut settings);
+        }
+    }
+
+    fn edit_menu(&mut self, ui: &mut egui::Ui) {
+        let Ok(mut settings) = self.settings.lock() else {
+            return;
+        };
+        let ctx = ui.ctx().clone();
+        let undo = egui::Button::new("Undo").shortcut_text(ctx.format_shortcut(&UNDO_SHORTCUT));
+        if ui.add_enabled(self.history.can_undo(), undo).clicked() {
+            self.history.undo(&mut settings);
+        }
+        let redo = egui::Button::new("Redo").shortcut_text(ctx.format_shortcut(&REDO_SHORTCUT));
+        if ui.add_enabled(self.history.can_redo(), redo).clicked() {
+            self.history.redo(&mut settings);
+        }
+        ui.separator();
+        let pin = egui::Button::new("Pin settings for compare");
+        if ui.add_enabled(!self.history.is_comparing(), pin).clicked() {
+            self.history.pin(&settings);
+            ui.close_menu();
+        }
+    }
+
+    /// Flips between the current and the pinned settings.
+    fn compare_toggle(&mut self, ui: &mut egui::Ui) {
+        let Ok(mut settings) = self.settings.lock() else {
+            return;
+        };
+        let comparing = self.history.is_comparing();
+        let label = if comparing {
+            "showing pinned"
+        } else {
+            "compare"
+        };
+        let toggle = egui::SelectableLabel::new(comparing, label);
+        if ui
+            .add_enabled(self.history.has_pinned(), toggle)
+            .on_disabled_hover_text("pin the settings from the Edit menu first")
+            .clicked()
+        {
+            self.history.toggle_compare(&mut settings);
+        }
+    }
+
+    /// Renders the current settings into an image file in the background.
+    fn start_export(&mut self, path: String) {
+        let Some(render_state) = &self.render_state else {
+            return;
+        };
+        // the viewport paint locks the renderer before the settings
+        let mut egui_renderer = render_state.renderer.write();
+        let Some(viewport) = render_view::viewport_renderer(&mut egui_renderer.callback_resources)
+        else {
+            return;
+        };
+        let Ok(settings) = self.settings.lock() else {
+            return;
+        };
+        self.export_error = None;
+        self.export = Some(ImageExport::start(
+            &render_state.device,
+            &render_state.queue,
+            viewport,
+            &settings,
+            path,
+            &self.export_options,
+        ));
+    }
+
+    fn poll_export(&mut self) {
+        let (Some(export), Some(render_state)) = (&mut self.export, &self.render_state) else {
+            return;
+        };
+        let Some(result) = export.poll(&render_state.device, &render_state.queue) else {
+            return;
+        };
+        match result {
+            Ok(()) => {
+                log::info!("Saved {}", export.path);
+                self.save_dialog_open = false;
+            }
+            Err(err) => {
+                log::error!("{}", err);
+                self.export_error = Some(err);
+            }
+        }
+        self.export = None;
+    }
+
+    fn save_dialog(&mut self, ctx: &egui::Context) {
+        let mut open = self.save_dialog_open;
+        egui::Window::new("Save image")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                if let Some(export) = &self.export {
+                    ui.add(
+                        egui::ProgressBar::new(export.progress())
+                            .text(format!("saving {}", export.path)),
+                    );
+                    return;
+                }
+                image_export::options_ui(ui, &mut self.export_options);
+                if let Some(err) = &self.export_error {
+                    ui.colored_label(ui.visuals().error_fg_color, err);
+                }
+                if ui.button("Save…").clicked() {
+                    if let Some(mut path) = rfd::FileDialog::new()
+                        .add_filter("PNG", &["png"])
+                        .add_filter("EXR", &["exr"])
+                        .save_file()
+                    {
+                        if path.extension().is_none() {
+                            path.set_extension("png");
+                        }
+                        self.start_export(path.display().to_string());
+                    }
+                }
+            });
+        // closing the window doesn't cancel a running export
+        self.save_dialog_open = open || self.export.is_some();
+    }
+
+    /// Advances playback and swaps in sequence frames once the loader has them.
+    fn update_sequences(&mut self, dt: f32) {
+        self.sequence_loader.poll();
+        let Ok(mut settings) = self.settings.lock() else {
+            return;
+        };
+        if !settings.is_animated() {
+            return;
+        }
+
+        let frame = settings.timeline.frame;
+        let mut frame_ready = true;
+        for index in 0..settings.volumes.len() {
+            let Some(sequence) = settings.volumes[index].sequence.clone() else {
+                continue;
+            };
+            let path = sequence.path(frame);
+            if settings.volumes[index].data.path != path {
+                let options = settings.load_options;
+                match self.sequence_loader.get(&path, &options) {
+                    Some(data) => settings.set_volume_data(index, data),
+                    // the previous frame stays on screen
+                    None if self.sequence_loader.has_failed(&path, &options) => {}
+                    None => frame_ready = false,
+                }
+            }
+            for offset in 1..=PREFETCH_FRAMES {
+                let next = settings.timeline.next_frame(frame, offset);
+                self.sequence_loader
+                    .request(&sequence.path(next), &settings.load_options);
+            }
+        }
+
+        // hold the playhead until the current frame is on screen
+        if frame_ready {
+            settings.timeline.advance(dt);
         }
     }
 }
 
 impl eframe::App for Editor {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if ctx.input_mut(|i| i.consume_shortcut(&SAVE_IMAGE_SHORTCUT)) {
+            self.save_dialog_open = true;
+        }
+        self.update_history(ctx);
         let input = ctx.input(|i| i.clone());
-        self.handle_key_down(input.keys_down);
-        self.handle_mouse(input.pointer);
+        self.update_bookmarks(&input, ctx.wants_keyboard_input());
+        // keys held with a shortcut modifier belong to the shortcut
+        if !input.modifiers.command {
+            self.handle_key_down(&input.keys_down);
+        }
+        self.handle_mouse(input.pointer.clone());
+        self.poll_loads();
+        self.update_sequences(input.stable_dt);
+        self.follow_camera_path();
+        self.poll_export();
         if let Ok(mut settings) = self.settings.try_lock() {
             settings.fps_ctrl.update();
         }
@@ -105,18 +459,34 @@ impl eframe::App for Editor {
             egui::menu::bar(ui, |ui| {
                 egui::menu::menu_button(ui, "File", |ui| {
                     if ui.button("Open").clicked() {
-                        if let Some(path) = rfd::FileDialog::new().pick_file() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("VDB", &["vdb"])
+                            .pick_file()
+                        {
                             if let Ok(mut settings) = self.settings.lock() {
-                                settings.picked_path = Some(path.display().to_string());
-
+                                settings.open_volume(&path.display().to_string());
                             }
                         }
                     }
 
+                    if ui
+                        .add(
+                            egui::Button::new("Save image…")
+                                .shortcut_text(ctx.format_shortcut(&SAVE_IMAGE_SHORTCUT)),
+                        )
+                        .clicked()
+                    {
+                        self.save_dialog_open = true;
+                        ui.close_menu();
+                    }
+
                     if ui.button("Quit").clicked() {
                         std::process::exit(0);
                     }
                 });
+                egui::menu::menu_button(ui, "Edit", |ui| self.edit_menu(ui));
+                ui.separator();
+                self.compare_toggle(ui);
             });
         });
         // egui::SidePanel::left("tree").show(ctx, |ui| {
@@ -131,9 +501,12 @@ impl eframe::App for Editor {
             let mut behavior = TreeBehavior {};
             self.tree.ui(&mut behavior, ui);
         });
+        self.save_dialog(ctx);
 
-        // TODO: high cpu usage here we need to repaint only render viewport
-        ctx.request_repaint();
+        // input repaints on its own, this keeps frames coming while nothing is pressed
+        if self.is_animating(&input) {
+            ctx.request_repaint();
+        }
     }
 
     fn clear_color(&self, visuals: &egui::Visuals) -> [f32; 4] {
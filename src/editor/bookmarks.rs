@@ -0,0 +1,100 @@
+use super::view::View;
+
+/// Number keys recall the first bookmarks.
+pub const MAX_KEYED_BOOKMARKS: usize = 9;
+const FILE_HEADER: &str = "# camera bookmarks: translation, rotation_x, rotation_y, name";
+
+pub struct Bookmark {
+    pub name: String,
+    pub view: View,
+}
+
+/// Named cameras of a VDB file, kept in a text file next to it so that everyone
+/// opening the file gets the same views.
+pub struct Bookmarks {
+    pub list: Vec<Bookmark>,
+    /// Where the bookmarks are saved, `None` until a volume is opened.
+    file: Option<String>,
+    /// Bookmark picked in the UI, the editor moves the camera to it.
+    recall: Option<usize>,
+}
+
+impl Bookmarks {
+    pub fn default() -> Self {
+        Self {
+            list: Vec::new(),
+            file: None,
+            recall: None,
+        }
+    }
+
+    /// Reads the bookmarks saved for a VDB file, a missing file means there are none yet.
+    pub fn load_for(vdb_path: &str) -> Self {
+        let file = format!("{}.bookmarks", vdb_path);
+        let mut bookmarks = Self {
+            file: Some(file.clone()),
+            ..Self::default()
+        };
+        let Ok(text) = std::fs::read_to_string(&file) else {
+            return bookmarks;
+        };
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.splitn(12, ' ');
+            match (View::parse(&mut fields), fields.next()) {
+                (Some(view), Some(name)) => bookmarks.list.push(Bookmark {
+                    name: name.to_string(),
+                    view,
+                }),
+                _ => log::warn!("Skipping invalid bookmark in {}: {}", file, line),
+            }
+        }
+        bookmarks
+    }
+
+    fn save(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let mut lines = vec![FILE_HEADER.to_string()];
+        for bookmark in &self.list {
+            lines.push(format!("{} {}", bookmark.view.to_text(), bookmark.name));
+        }
+        if let Err(err) = std::fs::write(file, lines.join("\n") + "\n") {
+            log::error!("Failed to write {}: {}", file, err);
+        }
+    }
+
+    /// Adds a bookmark, replacing the one with the same name.
+    pub fn add(&mut self, name: &str, view: View) {
+        let name = name.trim().to_string();
+        match self.list.iter_mut().find(|bookmark| bookmark.name == name) {
+            Some(bookmark) => bookmark.view = view,
+            None => self.list.push(Bookmark { name, view }),
+        }
+        self.save();
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.list.remove(index);
+        self.save();
+    }
+
+    pub fn recall(&mut self, index: usize) {
+        if index < self.list.len() {
+            self.recall = Some(index);
+        }
+    }
+
+    /// View of the bookmark picked since the last call.
+    pub fn take_recalled(&mut self) -> Option<View> {
+        let index = self.recall.take()?;
+        self.list.get(index).map(|bookmark| bookmark.view)
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.file.is_some()
+    }
+}
@@ -0,0 +1,174 @@
+use super::bookmarks::MAX_KEYED_BOOKMARKS;
+use super::camera_path::{CameraPath, TURNTABLE_KEYFRAMES};
+use super::settings::Settings;
+use super::view::View;
+use std::sync::{Arc, Mutex};
+
+const DEFAULT_TURNTABLE_FRAMES: u32 = 120;
+
+/// Keyframes of the camera path and the turntable generator.
+pub fn ui(ui: &mut egui::Ui, settings: &Arc<Mutex<Settings>>) {
+    let Ok(mut settings) = settings.lock() else {
+        ui.label("Failed to acquire settings lock.");
+        return;
+    };
+
+    ui.heading("Camera animation");
+    let mut changed = false;
+
+    let turntable_id = ui.id().with("turntable_frames");
+    let mut turntable_frames =
+        ui.data(|d| d.get_temp(turntable_id).unwrap_or(DEFAULT_TURNTABLE_FRAMES));
+    ui.horizontal(|ui| {
+        if ui.button("turntable").clicked() {
+            let view = View::from_matrix(&settings.matrix);
+            if let Err(err) = settings.set_turntable(&view, turntable_frames) {
+                log::error!("{}", err);
+            }
+        }
+        ui.add(
+            egui::DragValue::new(&mut turntable_frames)
+                .range(TURNTABLE_KEYFRAMES..=10000)
+                .suffix(" frames"),
+        );
+    });
+    ui.data_mut(|d| d.insert_temp(turntable_id, turntable_frames));
+
+    ui.horizontal(|ui| {
+        let frame = settings.timeline.frame;
+        if ui.button(format!("key frame {}", frame)).clicked() {
+            let view = View::from_matrix(&settings.matrix);
+            // the playhead is inside the timeline already
+            settings.camera_path.set_keyframe(frame, view);
+        }
+        ui.add_enabled_ui(!settings.camera_path.is_empty(), |ui| {
+            if ui.button("clear").clicked() {
+                settings.camera_path = CameraPath::default();
+                changed = true;
+            }
+        });
+    });
+    ui.checkbox(&mut settings.camera_path.preview, "follow path");
+
+    ui.horizontal(|ui| {
+        if ui.button("Load…").clicked() {
+            if let Some(file) = rfd::FileDialog::new()
+                .add_filter("Camera path", &["txt"])
+                .pick_file()
+            {
+                match CameraPath::load(&file.display().to_string()) {
+                    Ok(path) => {
+                        settings.camera_path = CameraPath {
+                            preview: true,
+                            ..path
+                        };
+                        changed = true;
+                    }
+                    Err(err) => log::error!("{}", err),
+                }
+            }
+        }
+        ui.add_enabled_ui(!settings.camera_path.is_empty(), |ui| {
+            if ui.button("Save…").clicked() {
+                if let Some(file) = rfd::FileDialog::new()
+                    .add_filter("Camera path", &["txt"])
+                    .save_file()
+                {
+                    if let Err(err) = settings.camera_path.save(&file.display().to_string()) {
+                        log::error!("{}", err);
+                    }
+                }
+            }
+        });
+    });
+
+    if settings.camera_path.closed {
+        ui.label(format!(
+            "turntable, {} keyframes",
+            settings.camera_path.keyframes.len()
+        ));
+    } else {
+        let mut remove = None;
+        let mut go_to = None;
+        egui::ScrollArea::vertical()
+            .max_height(160f32)
+            .show(ui, |ui| {
+                for (i, keyframe) in settings.camera_path.keyframes.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.button(format!("frame {}", keyframe.frame)).clicked() {
+                            go_to = Some(keyframe.frame);
+                        }
+                        if ui.small_button("🗑").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+            });
+        if let Some(frame) = go_to {
+            settings.timeline.frame = frame;
+            settings.timeline.playing = false;
+            settings.camera_path.preview = true;
+        }
+        if let Some(index) = remove {
+            settings.camera_path.remove_keyframe(index);
+            changed = true;
+        }
+    }
+
+    if changed {
+        settings.fit_timeline_to_camera_path();
+    }
+
+    ui.separator();
+    bookmarks_ui(ui, &mut settings);
+}
+
+/// Named cameras, the first nine are recalled with the number keys.
+fn bookmarks_ui(ui: &mut egui::Ui, settings: &mut Settings) {
+    ui.heading("Bookmarks");
+    if !settings.bookmarks.is_saved() {
+        ui.label("Open a VDB file to bookmark views of it.");
+        return;
+    }
+
+    let name_id = ui.id().with("bookmark_name");
+    let mut name: String = ui.data(|d| d.get_temp(name_id).unwrap_or_default());
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut name);
+        let default_name = format!("view {}", settings.bookmarks.list.len() + 1);
+        if ui.button("add").clicked() {
+            let view = View::from_matrix(&settings.matrix);
+            let name = if name.trim().is_empty() {
+                &default_name
+            } else {
+                &name
+            };
+            settings.bookmarks.add(name, view);
+        }
+    });
+    ui.data_mut(|d| d.insert_temp(name_id, name));
+
+    let mut remove = None;
+    let mut recall = None;
+    for (i, bookmark) in settings.bookmarks.list.iter().enumerate() {
+        ui.horizontal(|ui| {
+            let label = if i < MAX_KEYED_BOOKMARKS {
+                format!("{}: {}", i + 1, bookmark.name)
+            } else {
+                bookmark.name.clone()
+            };
+            if ui.button(label).clicked() {
+                recall = Some(i);
+            }
+            if ui.small_button("🗑").clicked() {
+                remove = Some(i);
+            }
+        });
+    }
+    if let Some(index) = recall {
+        settings.bookmarks.recall(index);
+    }
+    if let Some(index) = remove {
+        settings.bookmarks.remove(index);
+    }
+}
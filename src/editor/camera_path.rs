@@ -0,0 +1,279 @@
+use super::view::{slerp, View};
+use crate::aabb::Aabb;
+use glam::Vec3;
+use std::f32::consts::TAU;
+
+/// Keyframes placed around a turntable, enough for the spline to stay close to a circle.
+/// Also the fewest frames a turntable can have.
+pub const TURNTABLE_KEYFRAMES: u32 = 24;
+const FILE_HEADER: &str = "# camera path: frame, translation, rotation_x, rotation_y";
+
+#[derive(Clone, Copy)]
+pub struct CameraKeyframe {
+    pub frame: u32,
+    pub view: View,
+}
+
+/// Camera animation over the timeline. Rotations are slerped, translations follow
+/// a Catmull-Rom spline through the keyframes.
+pub struct CameraPath {
+    /// Sorted by frame, at most one per frame.
+    pub keyframes: Vec<CameraKeyframe>,
+    /// The last keyframe repeats the first, so it is left out of the range and the
+    /// spline continues around the loop.
+    pub closed: bool,
+    /// Drives the editor camera from the timeline.
+    pub preview: bool,
+}
+
+/// Hermite segment from `p1` to `p2` with Catmull-Rom tangents taken over the keyframe
+/// frames, so that the speed doesn't jump at keyframes that are unevenly spaced.
+fn catmull_rom(points: [Vec3; 4], frames: [f32; 4], t: f32) -> Vec3 {
+    let [p0, p1, p2, p3] = points;
+    let [f0, f1, f2, f3] = frames;
+    let duration = f2 - f1;
+    let m1 = (p2 - p0) * (duration / (f2 - f0));
+    let m2 = (p3 - p1) * (duration / (f3 - f1));
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (2f32 * t3 - 3f32 * t2 + 1f32) * p1
+        + (t3 - 2f32 * t2 + t) * m1
+        + (3f32 * t2 - 2f32 * t3) * p2
+        + (t3 - t2) * m2
+}
+
+impl CameraPath {
+    pub fn default() -> Self {
+        Self {
+            keyframes: Vec::new(),
+            closed: false,
+            preview: false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Frames covered by the keyframes.
+    pub fn frame_range(&self) -> Option<(u32, u32)> {
+        let first = self.keyframes.first()?.frame;
+        let last = self.keyframes.last()?.frame;
+        if self.closed && last > first {
+            Some((first, last - 1))
+        } else {
+            Some((first, last))
+        }
+    }
+
+    /// Adds a keyframe, replacing the one already on that frame.
+    pub fn set_keyframe(&mut self, frame: u32, view: View) {
+        let keyframe = CameraKeyframe { frame, view };
+        match self.keyframes.binary_search_by_key(&frame, |k| k.frame) {
+            Ok(index) => self.keyframes[index] = keyframe,
+            Err(index) => self.keyframes.insert(index, keyframe),
+        }
+        self.closed = false;
+    }
+
+    pub fn remove_keyframe(&mut self, index: usize) {
+        self.keyframes.remove(index);
+        self.closed = false;
+    }
+
+    /// Frame and position of a neighbour of a keyframe for the spline tangents,
+    /// wrapping around closed paths.
+    fn neighbour(&self, index: usize, offset: isize) -> (f32, Vec3) {
+        let count = self.keyframes.len() as isize;
+        let mut i = index as isize + offset;
+        let mut frame_offset = 0f32;
+        if self.closed && count > 2 {
+            // the first and last keyframes are the same point
+            let loop_frames =
+                (self.keyframes[count as usize - 1].frame - self.keyframes[0].frame) as f32;
+            if i < 0 {
+                i += count - 1;
+                frame_offset = -loop_frames;
+            } else if i >= count {
+                i -= count - 1;
+                frame_offset = loop_frames;
+            }
+        }
+        if i < 0 || i >= count {
+            // open ends repeat the keyframe one segment further out
+            let keyframe = &self.keyframes[index];
+            let inner = &self.keyframes[(index as isize - offset) as usize];
+            let frame = 2f32 * keyframe.frame as f32 - inner.frame as f32;
+            return (frame, keyframe.view.translation);
+        }
+        let keyframe = &self.keyframes[i as usize];
+        (
+            keyframe.frame as f32 + frame_offset,
+            keyframe.view.translation,
+        )
+    }
+
+    /// Camera on the given frame, held at the first and last keyframes outside of them.
+    pub fn view_at(&self, frame: f32) -> Option<View> {
+        let first = self.keyframes.first()?;
+        let last = self.keyframes.last()?;
+        if frame <= first.frame as f32 {
+            return Some(first.view);
+        }
+        if frame >= last.frame as f32 {
+            return Some(last.view);
+        }
+
+        let index = self
+            .keyframes
+            .partition_point(|k| (k.frame as f32) <= frame)
+            - 1;
+        let from = &self.keyframes[index];
+        let to = &self.keyframes[index + 1];
+        let t = (frame - from.frame as f32) / (to.frame - from.frame) as f32;
+        Some(View {
+            rotation_x: slerp(from.view.rotation_x, to.view.rotation_x, t),
+            rotation_y: slerp(from.view.rotation_y, to.view.rotation_y, t),
+            translation: {
+                let (frame0, p0) = self.neighbour(index, -1);
+                let (frame3, p3) = self.neighbour(index + 1, 1);
+                catmull_rom(
+                    [p0, from.view.translation, to.view.translation, p3],
+                    [frame0, from.frame as f32, to.frame as f32, frame3],
+                    t,
+                )
+            },
+        })
+    }
+
+    /// One turn around the center of the bbox over `frames` frames, starting from the
+    /// given view and keeping its pitch and distance. The keyframes can't be spaced evenly
+    /// for every frame count, their angles follow their frames so that the speed stays even.
+    pub fn turntable(bbox: &Aabb, view: &View, start: u32, frames: u32) -> Result<Self, String> {
+        if frames < TURNTABLE_KEYFRAMES {
+            return Err(format!(
+                "a turntable needs at least {} frames, got {}",
+                TURNTABLE_KEYFRAMES, frames
+            ));
+        }
+        let center = bbox.center();
+        let distance = (view.translation - center).length();
+        let view = if distance > 0f32 {
+            *view
+        } else {
+            View::framing(bbox)
+        };
+
+        let keyframes = (0..=TURNTABLE_KEYFRAMES)
+            .map(|i| {
+                let frame = frames * i / TURNTABLE_KEYFRAMES;
+                let angle = TAU * frame as f32 / frames as f32;
+                CameraKeyframe {
+                    frame: start + frame,
+                    view: view.orbit(center, angle),
+                }
+            })
+            .collect();
+        Ok(Self {
+            keyframes,
+            closed: true,
+            preview: true,
+        })
+    }
+
+    /// One keyframe per line, readable by `from_text`.
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![FILE_HEADER.to_string()];
+        if self.closed {
+            lines.push("closed".to_string());
+        }
+        for keyframe in &self.keyframes {
+            lines.push(format!("{} {}", keyframe.frame, keyframe.view.to_text()));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut path = Self::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line == "closed" {
+                path.closed = true;
+                continue;
+            }
+            let invalid = || format!("invalid camera keyframe on line {}", number + 1);
+            let mut fields = line.split_whitespace();
+            let frame: u32 = fields
+                .next()
+                .and_then(|field| field.parse().ok())
+                .ok_or_else(invalid)?;
+            let view = View::parse(&mut fields)
+                .filter(|_| fields.next().is_none())
+                .ok_or_else(invalid)?;
+            let closed = path.closed;
+            path.set_keyframe(frame, view);
+            path.closed = closed;
+        }
+        Ok(path)
+    }
+
+    pub fn load(file: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(file)
+            .map_err(|err| format!("Failed to read {}: {}", file, err))?;
+        Self::from_text(&text).map_err(|err| format!("{}: {}", file, err))
+    }
+
+    pub fn save(&self, file: &str) -> Result<(), String> {
+        std::fs::write(file, self.to_text())
+            .map_err(|err| format!("Failed to write {}: {}", file, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turntable(frames: u32) -> CameraPath {
+        let bbox = Aabb::from_min_max(Vec3::splat(-1f32), Vec3::splat(1f32));
+        CameraPath::turntable(&bbox, &View::framing(&bbox), 1, frames).unwrap()
+    }
+
+    #[test]
+    fn turntable_turns_at_an_even_speed() {
+        // 100 frames don't split into 24 equal segments
+        let path = turntable(100);
+        let angle = |frame: u32| {
+            let translation = path.view_at(frame as f32).unwrap().translation;
+            translation.z.atan2(translation.x)
+        };
+        let expected = TAU / 100f32;
+        for frame in 1..100 {
+            // either direction
+            let step = (angle(frame + 1) - angle(frame)).rem_euclid(TAU);
+            let step = step.min(TAU - step);
+            assert!(
+                (step - expected).abs() < expected * 0.01,
+                "frame {}: {} instead of {}",
+                frame,
+                step,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn turntable_covers_the_frames() {
+        assert_eq!(turntable(100).frame_range(), Some((1, 100)));
+        assert_eq!(turntable(TURNTABLE_KEYFRAMES).keyframes.len(), 25);
+    }
+
+    #[test]
+    fn rejects_short_turntables() {
+        let bbox = Aabb::from_min_max(Vec3::splat(-1f32), Vec3::splat(1f32));
+        let view = View::framing(&bbox);
+        assert!(CameraPath::turntable(&bbox, &view, 1, TURNTABLE_KEYFRAMES - 1).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use super::settings::Settings;
+use glam::{Mat4, Vec3};
+
+const HANDLE_LENGTH: f32 = 0.3f32;
+const HANDLE_RADIUS: f32 = 6f32;
+const PLANE_COLOR: egui::Color32 = egui::Color32::from_rgb(250, 179, 135);
+const ACTIVE_COLOR: egui::Color32 = egui::Color32::from_rgb(249, 226, 175);
+
+/// Maps a volume space point to the render pane, inverse of `get_ray` in compute.wgsl.
+pub fn project(world_to_camera: &Mat4, rect: egui::Rect, point: Vec3) -> Option<egui::Pos2> {
+    let local = world_to_camera.transform_point3(point);
+    if local.z <= 0f32 {
+        return None;
+    }
+
+    let ratio = rect.width() / rect.height();
+    let u = (local.x / local.z / ratio + 1f32) * 0.5f32;
+    let v = (1f32 - local.y / local.z) * 0.5f32;
+    Some(rect.min + egui::vec2(u * rect.width(), v * rect.height()))
+}
+
+/// Draws the enabled clipping planes over the render pane and lets the user drag
+/// their offset by the normal handle.
+pub fn show(ui: &egui::Ui, rect: egui::Rect, response: &egui::Response, settings: &mut Settings) {
+    let world_to_camera = settings.matrix.inverse();
+    let bbox = settings.scene_bbox();
+    let size = bbox.half_extent().length();
+    let handle_length = size * HANDLE_LENGTH;
+
+    let drag_id = response.id.with("clip_gizmo_drag");
+    let mut dragged: Option<usize> = ui.data(|d| d.get_temp(drag_id));
+
+    let painter = ui.painter_at(rect);
+
+    for (i, plane) in settings.clip_planes.iter_mut().enumerate() {
+        if !plane.enabled {
+            continue;
+        }
+
+        let normal = plane.unit_normal();
+        let origin = plane.origin(&bbox);
+        let (tangent, bitangent) = normal.any_orthonormal_pair();
+        let corners = [
+            origin + (tangent + bitangent) * size,
+            origin + (tangent - bitangent) * size,
+            origin - (tangent + bitangent) * size,
+            origin - (tangent - bitangent) * size,
+        ];
+
+        let color = if dragged == Some(i) {
+            ACTIVE_COLOR
+        } else {
+            PLANE_COLOR
+        };
+        let stroke = egui::Stroke::new(1.5f32, color);
+
+        let screen_corners: Option<Vec<egui::Pos2>> = corners
+            .iter()
+            .map(|&corner| project(&world_to_camera, rect, corner))
+            .collect();
+        if let Some(screen_corners) = screen_corners {
+            painter.add(egui::Shape::closed_line(screen_corners, stroke));
+        }
+
+        let (Some(origin_screen), Some(handle_screen)) = (
+            project(&world_to_camera, rect, origin),
+            project(&world_to_camera, rect, origin + normal * handle_length),
+        ) else {
+            continue;
+        };
+
+        painter.line_segment([origin_screen, handle_screen], stroke);
+        painter.circle_filled(handle_screen, HANDLE_RADIUS, color);
+
+        if response.drag_started() {
+            if let Some(pointer) = response.interact_pointer_pos() {
+                if pointer.distance(handle_screen) <= HANDLE_RADIUS * 2f32 {
+                    dragged = Some(i);
+                }
+            }
+        }
+
+        if dragged == Some(i) && response.dragged() {
+            let axis = handle_screen - origin_screen;
+            let support = bbox.half_extent().dot(normal.abs());
+            if axis.length() > 1f32 && support > 0f32 {
+                let pixels = response.drag_delta().dot(axis.normalized());
+                let distance = pixels / axis.length() * handle_length;
+                plane.offset = (plane.offset + distance / support).clamp(-1f32, 1f32);
+            }
+        }
+    }
+
+    if response.drag_stopped() {
+        dragged = None;
+    }
+
+    ui.data_mut(|d| match dragged {
+        Some(i) => d.insert_temp(drag_id, i),
+        None => d.remove::<usize>(drag_id),
+    });
+}
@@ -0,0 +1,257 @@
+use super::settings::Settings;
+use crate::volume_grid::{
+    DensitySource, GridClass, GridStatistics, LevelSetOptions, LoadOptions, Normalization,
+    Quantization, VolumeData, HISTOGRAM_BINS,
+};
+use std::sync::{Arc, Mutex};
+
+const HISTOGRAM_HEIGHT: f32 = 120f32;
+
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024f64 && unit < UNITS.len() - 1 {
+        value /= 1024f64;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+fn histogram_ui(ui: &mut egui::Ui, statistics: &GridStatistics) {
+    let (rect, response) = ui.allocate_exact_size(
+        egui::vec2(ui.available_width(), HISTOGRAM_HEIGHT),
+        egui::Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0f32, ui.visuals().extreme_bg_color);
+
+    // counts are drawn on a log scale, otherwise the empty space bin hides everything else
+    let log_count = |count: u64| (count as f32 + 1f32).ln();
+    let max_log = statistics
+        .histogram
+        .iter()
+        .map(|&count| log_count(count))
+        .fold(0f32, f32::max);
+    if max_log <= 0f32 {
+        return;
+    }
+
+    let bin_width = rect.width() / HISTOGRAM_BINS as f32;
+    let color = ui.visuals().selection.bg_fill;
+    for (bin, &count) in statistics.histogram.iter().enumerate() {
+        let height = log_count(count) / max_log * rect.height();
+        let x = rect.left() + bin as f32 * bin_width;
+        painter.rect_filled(
+            egui::Rect::from_min_max(
+                egui::pos2(x, rect.bottom() - height),
+                egui::pos2(x + bin_width - 1f32, rect.bottom()),
+            ),
+            0f32,
+            color,
+        );
+    }
+
+    if let Some(pointer) = response.hover_pos() {
+        let bin = ((pointer.x - rect.left()) / bin_width) as usize;
+        if bin < HISTOGRAM_BINS {
+            let (start, end) = statistics.bin_range(bin);
+            response.on_hover_text_at_pointer(format!(
+                "[{:.4}, {:.4}): {}",
+                start, end, statistics.histogram[bin]
+            ));
+        }
+    }
+}
+
+/// True while a slider is dragged or a value is typed, the options are applied once
+/// the edit is done.
+fn is_editing(response: &egui::Response) -> bool {
+    response.dragged() || response.has_focus()
+}
+
+/// Returns true while a value is being edited.
+fn normalization_ui(ui: &mut egui::Ui, normalization: &mut Normalization) -> bool {
+    egui::ComboBox::from_label("normalization")
+        .selected_text(normalization.label())
+        .show_ui(ui, |ui| {
+            for option in Normalization::ALL {
+                let selected =
+                    std::mem::discriminant(normalization) == std::mem::discriminant(&option);
+                if ui.selectable_label(selected, option.label()).clicked() && !selected {
+                    *normalization = option;
+                }
+            }
+        });
+
+    match normalization {
+        Normalization::Percentile(percent) => {
+            is_editing(&ui.add(egui::Slider::new(percent, 50.0..=100.0).text("percentile")))
+        }
+        Normalization::Range(low, high) => {
+            ui.horizontal(|ui| {
+                ui.label("range");
+                let low = ui.add(egui::DragValue::new(low).speed(0.01));
+                let high = ui.add(egui::DragValue::new(high).speed(0.01));
+                is_editing(&low) || is_editing(&high)
+            })
+            .inner
+        }
+        Normalization::Max | Normalization::Raw => false,
+    }
+}
+
+/// Returns true while a value is being edited.
+fn level_set_ui(ui: &mut egui::Ui, level_set: &mut LevelSetOptions) -> bool {
+    let density = ui.add(
+        egui::Slider::new(&mut level_set.interior_density, 0.0..=10.0)
+            .logarithmic(true)
+            .text("interior density"),
+    );
+    let falloff =
+        ui.add(egui::Slider::new(&mut level_set.falloff, 0.0..=3.0).text("band falloff (voxels)"));
+    is_editing(&density) || is_editing(&falloff)
+}
+
+/// Memory cost and error of every storage option, the selected one applies to all volumes.
+fn quantization_ui(ui: &mut egui::Ui, settings: &Arc<Mutex<Settings>>, volume: &VolumeData) {
+    let Ok(mut settings) = settings.lock() else {
+        return;
+    };
+    // options edited by a drag that is still going on
+    let draft_id = ui.id().with("load_options_draft");
+    let mut options = ui
+        .data(|data| data.get_temp::<LoadOptions>(draft_id))
+        .unwrap_or(settings.load_options);
+
+    if volume.velocity.is_some() {
+        egui::ComboBox::from_label("density source")
+            .selected_text(volume.source.label())
+            .show_ui(ui, |ui| {
+                for source in DensitySource::ALL {
+                    if volume.has_source(source) {
+                        ui.selectable_value(&mut options.source, source, source.label());
+                    }
+                }
+            });
+    }
+
+    // level sets are converted to fog instead of being normalized
+    let editing = match volume.dense.class {
+        GridClass::LevelSet => level_set_ui(ui, &mut options.level_set),
+        _ => normalization_ui(ui, &mut options.normalization),
+    };
+
+    ui.label("density storage");
+    egui::Grid::new("quantization")
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("");
+            ui.label("memory");
+            ui.label("rms error");
+            ui.label("lost voxels");
+            ui.end_row();
+            for (quantization, error) in Quantization::ALL.iter().zip(volume.quantization_errors) {
+                let quantized = LoadOptions {
+                    quantization: *quantization,
+                    ..options
+                };
+                let fits = settings.check_weights_size(&quantized, 0);
+                let selected = options.quantization == *quantization;
+                let response = ui.add_enabled(
+                    selected || fits.is_ok(),
+                    egui::RadioButton::new(selected, quantization.label()),
+                );
+                if response.clicked() {
+                    options.quantization = *quantization;
+                }
+                if let Err(err) = fits {
+                    response.on_disabled_hover_text(err);
+                }
+                let bytes = quantization.packed_bytes(volume.dense.values.len());
+                ui.label(format_bytes(bytes));
+                ui.label(format!("{:.2e}", error.rms));
+                ui.label(error.lost_voxels.to_string());
+                ui.end_row();
+            }
+        });
+
+    if let Some(repack) = &mut settings.repack {
+        ui.add(egui::ProgressBar::new(repack.progress()).text("repacking"));
+    }
+
+    if editing {
+        ui.data_mut(|data| data.insert_temp(draft_id, options));
+        return;
+    }
+    ui.data_mut(|data| data.remove::<LoadOptions>(draft_id));
+    if let Err(err) = settings.set_load_options(options) {
+        log::error!("Failed to repack the volumes: {}", err);
+    }
+}
+
+pub fn ui(ui: &mut egui::Ui, settings: &Arc<Mutex<Settings>>) {
+    let volume = settings
+        .lock()
+        .ok()
+        .and_then(|settings| settings.selected_volume_data());
+    let Some(volume) = volume else {
+        ui.label("No volume loaded.");
+        return;
+    };
+    let statistics = &volume.dense.statistics;
+
+    ui.heading(format!("Grid info: {}", volume.name));
+    egui::Grid::new("grid_info").striped(true).show(ui, |ui| {
+        ui.label("class");
+        ui.label(volume.dense.class.label());
+        ui.end_row();
+        if let Some(velocity) = &volume.velocity {
+            ui.label("max velocity");
+            ui.label(format!("{:.5}", velocity.max_magnitude));
+            ui.end_row();
+        }
+        ui.label("min");
+        ui.label(format!("{:.5}", statistics.min_value));
+        ui.end_row();
+        ui.label("max");
+        ui.label(format!("{:.5}", statistics.max_value));
+        ui.end_row();
+        ui.label("mean");
+        ui.label(format!("{:.5}", statistics.mean_value));
+        ui.end_row();
+        ui.label("active voxels");
+        ui.label(statistics.active_voxel_count.to_string());
+        ui.end_row();
+        ui.label("dense size");
+        ui.label(format!(
+            "{} x {} x {}",
+            volume.dense.size[0], volume.dense.size[1], volume.dense.size[2]
+        ));
+        ui.end_row();
+        ui.label("index bbox");
+        ui.label(format!(
+            "{} .. {}",
+            statistics.index_bbox.0, statistics.index_bbox.1
+        ));
+        ui.end_row();
+        ui.label("world bbox");
+        ui.label(format!(
+            "{:.3} .. {:.3}",
+            statistics.world_bbox.0, statistics.world_bbox.1
+        ));
+        ui.end_row();
+        ui.label("dense memory");
+        ui.label(format_bytes(statistics.dense_bytes));
+        ui.end_row();
+        ui.label("packed memory");
+        ui.label(format_bytes(volume.packed_bytes()));
+        ui.end_row();
+    });
+
+    ui.label("histogram (log scale)");
+    histogram_ui(ui, statistics);
+
+    ui.separator();
+    quantization_ui(ui, settings, &volume);
+}
@@ -0,0 +1,337 @@
+use super::settings::{
+    AdaptiveStep, ClipPlane, Material, OutputPass, RenderMode, Settings, ToneMapping,
+    VelocityOverlay, MAX_CLIP_PLANES,
+};
+use glam::Vec3;
+
+/// Placement and look of one volume.
+#[derive(Clone, Copy, PartialEq)]
+struct VolumeState {
+    visible: bool,
+    translation: Vec3,
+    rotation: Vec3,
+    scale: f32,
+    material: Material,
+}
+
+/// The part of the settings the user edits in the panes. Loaded volumes, load options
+/// and the camera are left out.
+#[derive(Clone, PartialEq)]
+pub struct SettingsState {
+    background_color: Vec3,
+    transparent_background: bool,
+    light_color: Vec3,
+    lightness: f32,
+    ray_marching_step: f32,
+    adaptive_step: AdaptiveStep,
+    light_cache: bool,
+    tone_mapping: ToneMapping,
+    display_pass: OutputPass,
+    render_mode: RenderMode,
+    iso_value: f32,
+    density_scale: f32,
+    density_gamma: f32,
+    density_contrast: f32,
+    density_invert: bool,
+    clip_planes: [ClipPlane; MAX_CLIP_PLANES],
+    cap_clip_planes: bool,
+    velocity_overlay: VelocityOverlay,
+    volumes: Vec<VolumeState>,
+}
+
+impl SettingsState {
+    pub fn capture(settings: &Settings) -> Self {
+        Self {
+            background_color: settings.background_color,
+            transparent_background: settings.transparent_background,
+            light_color: settings.light_color,
+            lightness: settings.lightness,
+            ray_marching_step: settings.ray_marching_step,
+            adaptive_step: settings.adaptive_step,
+            light_cache: settings.light_cache,
+            tone_mapping: settings.tone_mapping,
+            display_pass: settings.display_pass,
+            render_mode: settings.render_mode,
+            iso_value: settings.iso_value,
+            density_scale: settings.density_scale,
+            density_gamma: settings.density_gamma,
+            density_contrast: settings.density_contrast,
+            density_invert: settings.density_invert,
+            clip_planes: settings.clip_planes,
+            cap_clip_planes: settings.cap_clip_planes,
+            velocity_overlay: settings.velocity_overlay,
+            volumes: settings
+                .volumes
+                .iter()
+                .map(|volume| VolumeState {
+                    visible: volume.visible,
+                    translation: volume.translation,
+                    rotation: volume.rotation,
+                    scale: volume.scale,
+                    material: volume.material,
+                })
+                .collect(),
+        }
+    }
+
+    /// Restores the state, the volumes only if none were added or removed since.
+    pub fn apply(&self, settings: &mut Settings) {
+        settings.background_color = self.background_color;
+        settings.transparent_background = self.transparent_background;
+        settings.light_color = self.light_color;
+        settings.lightness = self.lightness;
+        settings.ray_marching_step = self.ray_marching_step;
+        settings.adaptive_step = self.adaptive_step;
+        settings.light_cache = self.light_cache;
+        settings.tone_mapping = self.tone_mapping;
+        settings.display_pass = self.display_pass;
+        settings.render_mode = self.render_mode;
+        settings.iso_value = self.iso_value;
+        settings.density_scale = self.density_scale;
+        settings.density_gamma = self.density_gamma;
+        settings.density_contrast = self.density_contrast;
+        settings.density_invert = self.density_invert;
+        settings.clip_planes = self.clip_planes;
+        settings.cap_clip_planes = self.cap_clip_planes;
+        settings.velocity_overlay = self.velocity_overlay;
+        if settings.volumes.len() == self.volumes.len() {
+            for (volume, state) in settings.volumes.iter_mut().zip(&self.volumes) {
+                volume.visible = state.visible;
+                volume.translation = state.translation;
+                volume.rotation = state.rotation;
+                volume.scale = state.scale;
+                volume.material = state.material;
+            }
+        }
+    }
+}
+
+/// Undo and redo over settings edits, found by comparing the settings every frame.
+pub struct History {
+    undo: Vec<SettingsState>,
+    redo: Vec<SettingsState>,
+    /// State after the last recorded edit.
+    current: Option<SettingsState>,
+    /// Changes made while the pointer stays down, like a slider drag, become one entry.
+    merging: bool,
+    pinned: Option<SettingsState>,
+    /// The user's state while the pinned one is shown.
+    compared: Option<SettingsState>,
+}
+
+/// Oldest entries are dropped past this.
+const MAX_UNDO: usize = 200;
+
+impl History {
+    pub fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            current: None,
+            merging: false,
+            pinned: None,
+            compared: None,
+        }
+    }
+
+    /// Records the settings as changed since the last call, if they did.
+    pub fn record(&mut self, settings: &Settings, pointer_down: bool) {
+        if self.is_comparing() {
+            return;
+        }
+        let state = SettingsState::capture(settings);
+        let Some(current) = &self.current else {
+            self.current = Some(state);
+            return;
+        };
+        if *current == state {
+            self.merging &= pointer_down;
+            return;
+        }
+        // loading or removing volumes isn't an edit to undo
+        if current.volumes.len() == state.volumes.len() && !self.merging {
+            self.undo.push(current.clone());
+            if self.undo.len() > MAX_UNDO {
+                self.undo.remove(0);
+            }
+            self.redo.clear();
+        }
+        self.merging = pointer_down;
+        self.current = Some(state);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() && !self.is_comparing()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty() && !self.is_comparing()
+    }
+
+    pub fn undo(&mut self, settings: &mut Settings) {
+        if !self.can_undo() {
+            return;
+        }
+        let state = self.undo.pop().unwrap();
+        state.apply(settings);
+        self.redo.extend(self.current.replace(state));
+        self.merging = false;
+    }
+
+    pub fn redo(&mut self, settings: &mut Settings) {
+        if !self.can_redo() {
+            return;
+        }
+        let state = self.redo.pop().unwrap();
+        state.apply(settings);
+        self.undo.extend(self.current.replace(state));
+        self.merging = false;
+    }
+
+    /// Keeps the current settings to compare later edits against.
+    pub fn pin(&mut self, settings: &Settings) {
+        if !self.is_comparing() {
+            self.pinned = Some(SettingsState::capture(settings));
+        }
+    }
+
+    pub fn has_pinned(&self) -> bool {
+        self.pinned.is_some()
+    }
+
+    pub fn is_comparing(&self) -> bool {
+        self.compared.is_some()
+    }
+
+    /// Swaps between the current and the pinned settings. Edits made while the pinned
+    /// settings are shown are dropped when switching back.
+    pub fn toggle_compare(&mut self, settings: &mut Settings) {
+        if let Some(state) = self.compared.take() {
+            state.apply(settings);
+        } else if let Some(pinned) = &self.pinned {
+            self.compared = Some(SettingsState::capture(settings));
+            pinned.apply(settings);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::volume_grid::{LoadOptions, VolumeData};
+    use std::sync::Arc;
+
+    fn load_volume() -> Arc<VolumeData> {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/data/vdbAssets/smoke.vdb");
+        let data = VolumeData::load(path, &LoadOptions::default())
+            .unwrap_or_else(|_| panic!("failed to load {}", path));
+        Arc::new(data)
+    }
+
+    /// An edit that is done in one frame, like a click.
+    fn edit(history: &mut History, settings: &mut Settings, density_scale: f32) {
+        settings.density_scale = density_scale;
+        history.record(settings, false);
+    }
+
+    #[test]
+    fn drag_is_one_entry() {
+        let mut settings = Settings::default();
+        let mut history = History::default();
+        history.record(&settings, false);
+        let start = settings.density_scale;
+
+        for value in [2f32, 3f32, 4f32] {
+            settings.density_scale = value;
+            history.record(&settings, true);
+        }
+        history.record(&settings, false);
+        assert!(history.can_undo());
+
+        history.undo(&mut settings);
+        assert_eq!(settings.density_scale, start);
+        assert!(!history.can_undo());
+        history.redo(&mut settings);
+        assert_eq!(settings.density_scale, 4f32);
+    }
+
+    #[test]
+    fn edits_after_a_drag_are_separate() {
+        let mut settings = Settings::default();
+        let mut history = History::default();
+        history.record(&settings, false);
+
+        settings.density_scale = 2f32;
+        history.record(&settings, true);
+        history.record(&settings, false);
+        edit(&mut history, &mut settings, 3f32);
+
+        history.undo(&mut settings);
+        assert_eq!(settings.density_scale, 2f32);
+        assert!(history.can_undo());
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut settings = Settings::default();
+        let mut history = History::default();
+        history.record(&settings, false);
+        edit(&mut history, &mut settings, 2f32);
+        edit(&mut history, &mut settings, 3f32);
+
+        history.undo(&mut settings);
+        assert!(history.can_redo());
+        edit(&mut history, &mut settings, 5f32);
+        assert!(!history.can_redo());
+
+        history.undo(&mut settings);
+        assert_eq!(settings.density_scale, 2f32);
+    }
+
+    #[test]
+    fn adding_and_removing_volumes_is_not_recorded() {
+        let mut settings = Settings::default();
+        let mut history = History::default();
+        history.record(&settings, false);
+
+        settings.add_volume(load_volume());
+        history.record(&settings, false);
+        assert!(!history.can_undo());
+
+        settings.volumes[0].scale = 2f32;
+        history.record(&settings, false);
+        history.undo(&mut settings);
+        assert_eq!(settings.volumes[0].scale, 1f32);
+        history.redo(&mut settings);
+
+        settings.remove_volume(0);
+        history.record(&settings, false);
+        history.undo(&mut settings);
+        assert!(settings.volumes.is_empty());
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn edits_while_comparing_are_dropped() {
+        let mut settings = Settings::default();
+        let mut history = History::default();
+        history.record(&settings, false);
+        let start = settings.density_scale;
+        history.pin(&settings);
+        edit(&mut history, &mut settings, 2f32);
+
+        history.toggle_compare(&mut settings);
+        assert!(history.is_comparing());
+        assert_eq!(settings.density_scale, start);
+        assert!(!history.can_undo());
+        edit(&mut history, &mut settings, 7f32);
+
+        history.toggle_compare(&mut settings);
+        assert!(!history.is_comparing());
+        assert_eq!(settings.density_scale, 2f32);
+        history.record(&settings, false);
+        history.undo(&mut settings);
+        assert_eq!(settings.density_scale, start);
+        assert!(!history.can_undo());
+    }
+}
@@ -0,0 +1,339 @@
+use super::settings::Settings;
+use crate::image_file::{is_exr, save_exr, save_png, split_rgba16f};
+use crate::renderer::{Readback, VolumeRenderer, CHANNEL_NAMES};
+use crate::tone_mapping::ToneMapper;
+use crate::SCREEN_SIZE;
+use eframe::wgpu;
+use half::f16;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Largest side of a saved image, bounded by the texture size limit of most adapters.
+pub const MAX_EXPORT_SIZE: u32 = 8192;
+pub const MAX_EXPORT_SAMPLES: u32 = 256;
+
+/// Chosen in the save dialog.
+#[derive(Clone, Copy)]
+pub struct ExportOptions {
+    pub width: u32,
+    pub height: u32,
+    /// Jittered samples averaged per pixel.
+    pub samples: u32,
+    /// Stores the settings summary as PNG text chunks.
+    pub embed_settings: bool,
+}
+
+impl ExportOptions {
+    pub fn default() -> Self {
+        Self {
+            width: SCREEN_SIZE[0],
+            height: SCREEN_SIZE[1],
+            samples: 1,
+            embed_settings: true,
+        }
+    }
+}
+
+/// Radical inverse of `index` in `base`, low discrepancy points in [0, 1).
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0f32;
+    let mut fraction = 1f32;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Ray position within the pixel for a sample, the first one goes through the corner
+/// like the viewport does.
+fn sample_offset(sample: u32) -> [f32; 2] {
+    if sample == 0 {
+        return [0f32; 2];
+    }
+    [halton(sample, 2), halton(sample, 3)]
+}
+
+/// Renders an image for saving. A single sample at the size of the viewport is read back
+/// from the viewport's render, other sizes and sample counts get their own renderer that
+/// shares the viewport's weights. One sample is in flight at a time and is read back
+/// without blocking the UI.
+pub struct ImageExport {
+    pub path: String,
+    width: u32,
+    height: u32,
+    /// None when the image is the viewport's render.
+    renderer: Option<VolumeRenderer>,
+    tone_mapper: ToneMapper,
+    output_texture: wgpu::Texture,
+    output_view: wgpu::TextureView,
+    text: Vec<(String, String)>,
+    samples: u32,
+    /// Samples read back and added to `sums` so far.
+    finished_samples: u32,
+    /// Readbacks of the sample on the GPU and how many of them are mapped.
+    pending: Vec<Readback>,
+    mapped: Arc<AtomicUsize>,
+    failed: Arc<AtomicUsize>,
+    /// Per read texture, the summed components of all finished samples.
+    sums: Vec<Vec<f32>>,
+}
+
+impl ImageExport {
+    /// Uploads the current settings and starts the first sample.
+    pub fn start(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        viewport: &mut VolumeRenderer,
+        settings: &Settings,
+        path: String,
+        options: &ExportOptions,
+    ) -> Self {
+        let width = options.width.clamp(1, MAX_EXPORT_SIZE);
+        let height = options.height.clamp(1, MAX_EXPORT_SIZE);
+        let samples = options.samples.clamp(1, MAX_EXPORT_SAMPLES);
+        let mut renderer = (samples > 1 || width != viewport.width || height != viewport.height)
+            .then(|| VolumeRenderer::sharing_weights(device, width, height, viewport));
+
+        // the settings may change while the samples render, so they are uploaded only once
+        match &mut renderer {
+            Some(renderer) => {
+                renderer.ray_stats = false;
+                renderer.prepare(device, queue, settings);
+            }
+            None => {
+                if viewport.prepare(device, queue, settings) {
+                    let mut encoder =
+                        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Export encoder"),
+                        });
+                    viewport.dispatch(&mut encoder, None);
+                    queue.submit(Some(encoder.finish()));
+                }
+            }
+        }
+
+        let output_format = wgpu::TextureFormat::Rgba8Unorm;
+        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Export texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: output_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let source = renderer.as_ref().unwrap_or(viewport);
+        let tone_mapper = ToneMapper::new(device, source, output_format, true);
+        tone_mapper.prepare(queue, settings);
+
+        let text = if options.embed_settings && !is_exr(&path) {
+            vec![
+                ("Software".to_string(), "render".to_string()),
+                ("Comment".to_string(), settings.summary()),
+            ]
+        } else {
+            Vec::new()
+        };
+
+        let mut export = Self {
+            path,
+            width,
+            height,
+            renderer,
+            tone_mapper,
+            output_texture,
+            output_view,
+            text,
+            samples,
+            finished_samples: 0,
+            pending: Vec::new(),
+            mapped: Arc::new(AtomicUsize::new(0)),
+            failed: Arc::new(AtomicUsize::new(0)),
+            sums: Vec::new(),
+        };
+        export.submit_sample(device, queue, Some(viewport));
+        export
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.finished_samples as f32 / self.samples as f32
+    }
+
+    /// Renders the next sample with the export's own renderer, or reads back `viewport`.
+    fn submit_sample(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        viewport: Option<&mut VolumeRenderer>,
+    ) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Export encoder"),
+        });
+        let renderer = match (&mut self.renderer, viewport) {
+            (Some(renderer), _) => {
+                renderer.set_pixel_offset(queue, sample_offset(self.finished_samples));
+                renderer.dispatch(&mut encoder, None);
+                renderer
+            }
+            (None, Some(viewport)) => viewport,
+            (None, None) => return,
+        };
+        self.pending = if is_exr(&self.path) {
+            renderer
+                .channel_textures()
+                .iter()
+                .map(|(texture, _)| Readback::new(device, &mut encoder, texture))
+                .collect()
+        } else {
+            self.tone_mapper
+                .render(&mut encoder, &self.output_view, None);
+            vec![Readback::new(device, &mut encoder, &self.output_texture)]
+        };
+        queue.submit(Some(encoder.finish()));
+
+        self.mapped.store(0, Ordering::SeqCst);
+        for readback in &self.pending {
+            let mapped = self.mapped.clone();
+            let failed = self.failed.clone();
+            readback.map(move |result| {
+                if let Err(err) = result {
+                    log::error!("Failed to map export buffer: {}", err);
+                    failed.fetch_add(1, Ordering::SeqCst);
+                }
+                mapped.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+    }
+
+    /// Adds a read back sample to the sums. Tone mapped colors are weighted by their
+    /// alpha, so that transparent pixels don't darken the edges.
+    fn accumulate(&mut self) {
+        let exr = is_exr(&self.path);
+        let readbacks = std::mem::take(&mut self.pending);
+        for (i, readback) in readbacks.iter().enumerate() {
+            let components: Vec<f32> = if exr {
+                readback
+                    .read()
+                    .chunks_exact(2)
+                    .map(|bytes| f16::from_le_bytes([bytes[0], bytes[1]]).to_f32())
+                    .collect()
+            } else {
+                readback
+                    .read()
+                    .chunks_exact(4)
+                    .flat_map(|pixel| {
+                        let alpha = pixel[3] as f32;
+                        [
+                            pixel[0] as f32 * alpha,
+                            pixel[1] as f32 * alpha,
+                            pixel[2] as f32 * alpha,
+                            alpha,
+                        ]
+                    })
+                    .collect()
+            };
+            match self.sums.get_mut(i) {
+                Some(sum) => sum
+                    .iter_mut()
+                    .zip(components)
+                    .for_each(|(sum, value)| *sum += value),
+                None => self.sums.push(components),
+            }
+        }
+        self.finished_samples += 1;
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let samples = self.samples as f32;
+        if is_exr(&self.path) {
+            let channels = CHANNEL_NAMES
+                .iter()
+                .zip(&self.sums)
+                .flat_map(|(names, sum)| {
+                    let bytes: Vec<u8> = sum
+                        .iter()
+                        .flat_map(|value| f16::from_f32(value / samples).to_le_bytes())
+                        .collect();
+                    split_rgba16f(&bytes, *names)
+                })
+                .collect();
+            save_exr(&self.path, self.width, self.height, channels)
+        } else {
+            let pixels: Vec<u8> = self.sums[0]
+                .chunks_exact(4)
+                .flat_map(|pixel| {
+                    let alpha = pixel[3];
+                    let color = |value: f32| {
+                        if alpha > 0f32 {
+                            (value / alpha).round() as u8
+                        } else {
+                            0u8
+                        }
+                    };
+                    [
+                        color(pixel[0]),
+                        color(pixel[1]),
+                        color(pixel[2]),
+                        (alpha / samples).round() as u8,
+                    ]
+                })
+                .collect();
+            save_png(&self.path, self.width, self.height, &pixels, &self.text)
+        }
+    }
+
+    /// Collects the sample once it is mapped and starts the next one.
+    /// Returns the outcome after the last sample is saved.
+    pub fn poll(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Option<Result<(), String>> {
+        device.poll(wgpu::Maintain::Poll);
+        if self.mapped.load(Ordering::SeqCst) < self.pending.len() {
+            return None;
+        }
+        if self.failed.load(Ordering::SeqCst) > 0 {
+            return Some(Err(format!("Failed to read back {}", self.path)));
+        }
+
+        self.accumulate();
+        if self.finished_samples < self.samples {
+            self.submit_sample(device, queue, None);
+            return None;
+        }
+        Some(self.save())
+    }
+}
+
+/// Size, sample count and metadata of the saved image.
+pub fn options_ui(ui: &mut egui::Ui, options: &mut ExportOptions) {
+    ui.horizontal(|ui| {
+        ui.label("resolution");
+        ui.add(egui::DragValue::new(&mut options.width).range(1..=MAX_EXPORT_SIZE));
+        ui.label("×");
+        ui.add(egui::DragValue::new(&mut options.height).range(1..=MAX_EXPORT_SIZE));
+    });
+    ui.horizontal(|ui| {
+        for scale in [1, 2, 4] {
+            if ui.button(format!("{}×", scale)).clicked() {
+                options.width = SCREEN_SIZE[0] * scale;
+                options.height = SCREEN_SIZE[1] * scale;
+            }
+        }
+    });
+    ui.add(
+        egui::Slider::new(&mut options.samples, 1..=MAX_EXPORT_SAMPLES)
+            .logarithmic(true)
+            .text("samples per pixel"),
+    );
+    ui.checkbox(&mut options.embed_settings, "embed settings (PNG)");
+}
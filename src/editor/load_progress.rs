@@ -0,0 +1,49 @@
+use super::settings::Settings;
+use crate::loader::LoadStage;
+
+const PANEL_WIDTH: f32 = 300f32;
+const MARGIN: f32 = 8f32;
+
+/// Draws the progress of background loads over the bottom of the viewport.
+pub fn show(ui: &mut egui::Ui, rect: egui::Rect, settings: &mut Settings) {
+    if settings.loads.is_empty() {
+        return;
+    }
+
+    let panel_rect = egui::Rect::from_min_max(
+        egui::pos2(rect.min.x + MARGIN, rect.center().y),
+        egui::pos2(rect.min.x + MARGIN + PANEL_WIDTH, rect.max.y - MARGIN),
+    );
+
+    let mut cancelled = None;
+    ui.allocate_new_ui(
+        egui::UiBuilder::new()
+            .max_rect(panel_rect)
+            .layout(egui::Layout::bottom_up(egui::Align::Min)),
+        |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                for (i, task) in settings.loads.iter_mut().enumerate() {
+                    let (stage, fraction) = task.progress();
+                    ui.horizontal(|ui| {
+                        ui.label(&task.name);
+                        // the data is already in the scene while uploading
+                        if stage != LoadStage::Uploading && ui.small_button("Cancel").clicked() {
+                            cancelled = Some(i);
+                        }
+                    });
+                    ui.add(
+                        egui::ProgressBar::new(fraction)
+                            .desired_width(PANEL_WIDTH)
+                            .text(stage.label())
+                            .animate(stage == LoadStage::Uploading),
+                    );
+                }
+            });
+        },
+    );
+
+    // dropping the task stops its worker
+    if let Some(index) = cancelled {
+        settings.loads.remove(index);
+    }
+}
@@ -0,0 +1,95 @@
+use super::settings::{Settings, VolumeInstance, MAX_VOLUMES};
+use std::sync::{Arc, Mutex};
+
+fn vec3_ui(ui: &mut egui::Ui, label: &str, value: &mut glam::Vec3, speed: f32) {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        ui.add(egui::DragValue::new(&mut value.x).speed(speed));
+        ui.add(egui::DragValue::new(&mut value.y).speed(speed));
+        ui.add(egui::DragValue::new(&mut value.z).speed(speed));
+    });
+}
+
+fn instance_ui(ui: &mut egui::Ui, volume: &mut VolumeInstance) {
+    let speed = volume.data.grid.min_voxel_size();
+    vec3_ui(ui, "translation", &mut volume.translation, speed);
+    vec3_ui(ui, "rotation", &mut volume.rotation, 1f32);
+    ui.add(
+        egui::DragValue::new(&mut volume.scale)
+            .speed(0.01)
+            .range(0.01..=100.0)
+            .prefix("scale "),
+    );
+
+    let material = &mut volume.material;
+    ui.add(egui::Slider::new(&mut material.g, -1.0..=1.0).text("g"));
+    ui.add(egui::Slider::new(&mut material.absorption, 0.0..=0.1).text("absorption"));
+    ui.add(egui::Slider::new(&mut material.scattering, 0.0..=0.5).text("scattering"));
+    ui.horizontal(|ui| {
+        ui.color_edit_button_rgb(material.emission_color.as_mut());
+        ui.add(
+            egui::Slider::new(&mut material.emission, 0.0..=1.0)
+                .logarithmic(true)
+                .text("emission"),
+        );
+    });
+}
+
+pub fn ui(ui: &mut egui::Ui, settings: &Arc<Mutex<Settings>>) {
+    let volume_count = settings
+        .lock()
+        .map(|s| s.volumes.len() + s.loads.len())
+        .unwrap_or(0);
+
+    ui.heading("Outliner");
+    let add_button = ui.add_enabled(
+        volume_count < MAX_VOLUMES,
+        egui::Button::new("Add volume..."),
+    );
+    if add_button.clicked() {
+        if let Some(path) = rfd::FileDialog::new()
+            .add_filter("VDB", &["vdb"])
+            .pick_file()
+        {
+            if let Ok(mut settings) = settings.lock() {
+                settings.open_volume(&path.display().to_string());
+            }
+        }
+    }
+
+    let Ok(mut settings) = settings.lock() else {
+        ui.label("Failed to acquire settings lock.");
+        return;
+    };
+
+    let mut to_remove = None;
+    let mut selected = settings.selected_volume;
+    for (i, volume) in settings.volumes.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut volume.visible, "");
+            if ui
+                .selectable_label(selected == Some(i), &volume.data.name)
+                .clicked()
+            {
+                selected = Some(i);
+            }
+            if ui.small_button("🗑").clicked() {
+                to_remove = Some(i);
+            }
+        });
+    }
+    settings.selected_volume = selected;
+
+    if let Some(index) = to_remove {
+        settings.remove_volume(index);
+    }
+
+    ui.separator();
+    let selected = settings.selected_volume;
+    match selected.and_then(|index| settings.volumes.get_mut(index)) {
+        Some(volume) => instance_ui(ui, volume),
+        None => {
+            ui.label("Select a volume to edit its transform and material.");
+        }
+    }
+}
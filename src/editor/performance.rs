@@ -0,0 +1,237 @@
+use super::settings::Settings;
+use crate::profiler::{GpuFrame, TimedPass};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Renders kept for the graph.
+const HISTORY_LENGTH: usize = 240;
+const GRAPH_HEIGHT: f32 = 100f32;
+
+/// Measurements of one render of the volumes.
+#[derive(Clone, Copy)]
+struct FrameTimings {
+    compute_ms: Option<f32>,
+    blit_ms: Option<f32>,
+    /// CPU time spent writing the uniforms and uploading the volumes.
+    prepare_ms: f32,
+    samples_per_ray: f32,
+    light_samples_per_ray: f32,
+    early_terminated_percent: f32,
+}
+
+/// Timings of the last renders, filled in by the render view.
+pub struct Performance {
+    history: VecDeque<FrameTimings>,
+    /// `None` until the renderer reported whether the adapter supports timestamp queries.
+    timestamps: Option<bool>,
+    timestamps_inside_passes: bool,
+    /// CPU time of the render the next GPU measurements belong to.
+    prepare_ms: f32,
+    /// Last volume upload, they only happen when volumes are added or removed.
+    upload_ms: Option<f32>,
+    /// Measurements are still on their way back from the GPU.
+    pub pending: bool,
+}
+
+fn to_ms(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000f32
+}
+
+impl Performance {
+    pub fn default() -> Self {
+        Self {
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
+            timestamps: None,
+            timestamps_inside_passes: false,
+            prepare_ms: 0f32,
+            upload_ms: None,
+            pending: false,
+        }
+    }
+
+    pub fn set_timestamp_support(&mut self, timestamps: bool, inside_passes: bool) {
+        self.timestamps = Some(timestamps);
+        self.timestamps_inside_passes = inside_passes;
+    }
+
+    /// CPU timings of a render about to be dispatched.
+    pub fn record_prepare(&mut self, prepare: Duration, upload: Option<Duration>) {
+        self.prepare_ms = to_ms(prepare);
+        if let Some(upload) = upload {
+            self.upload_ms = Some(to_ms(upload));
+        }
+    }
+
+    pub fn record_gpu(&mut self, frame: &GpuFrame) {
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(FrameTimings {
+            compute_ms: frame.pass_ms(TimedPass::Compute),
+            blit_ms: frame.pass_ms(TimedPass::Blit),
+            prepare_ms: self.prepare_ms,
+            samples_per_ray: frame.stats.samples_per_ray(),
+            light_samples_per_ray: frame.stats.light_samples_per_ray(),
+            early_terminated_percent: frame.stats.early_terminated_percent(),
+        });
+    }
+
+    fn average(&self, value: impl Fn(&FrameTimings) -> Option<f32>) -> Option<f32> {
+        let values: Vec<f32> = self.history.iter().filter_map(value).collect();
+        (!values.is_empty()).then(|| values.iter().sum::<f32>() / values.len() as f32)
+    }
+}
+
+/// Series drawn in the graph, with their colors.
+const SERIES: [(&str, egui::Color32); 3] = [
+    ("compute", egui::Color32::from_rgb(230, 120, 60)),
+    ("blit", egui::Color32::from_rgb(90, 170, 230)),
+    ("prepare (CPU)", egui::Color32::from_rgb(140, 200, 90)),
+];
+
+fn series_value(timings: &FrameTimings, series: usize) -> Option<f32> {
+    match series {
+        0 => timings.compute_ms,
+        1 => timings.blit_ms,
+        _ => Some(timings.prepare_ms),
+    }
+}
+
+fn graph_ui(ui: &mut egui::Ui, performance: &Performance) {
+    let (rect, response) = ui.allocate_exact_size(
+        egui::vec2(ui.available_width(), GRAPH_HEIGHT),
+        egui::Sense::hover(),
+    );
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0f32, ui.visuals().extreme_bg_color);
+
+    let max_ms = performance
+        .history
+        .iter()
+        .flat_map(|timings| (0..SERIES.len()).filter_map(|i| series_value(timings, i)))
+        .fold(0f32, f32::max);
+    if max_ms <= 0f32 {
+        return;
+    }
+    // round the scale up so that it doesn't jump every frame
+    let scale_ms = 2f32.powf(max_ms.log2().ceil());
+
+    let step = rect.width() / (HISTORY_LENGTH - 1) as f32;
+    let first = HISTORY_LENGTH - performance.history.len();
+    for (series, (_, color)) in SERIES.iter().enumerate() {
+        let points: Vec<egui::Pos2> = performance
+            .history
+            .iter()
+            .enumerate()
+            .filter_map(|(i, timings)| {
+                let ms = series_value(timings, series)?;
+                Some(egui::pos2(
+                    rect.left() + (first + i) as f32 * step,
+                    rect.bottom() - ms / scale_ms * rect.height(),
+                ))
+            })
+            .collect();
+        painter.add(egui::Shape::line(points, egui::Stroke::new(1.5f32, *color)));
+    }
+    painter.text(
+        rect.left_top() + egui::vec2(4f32, 2f32),
+        egui::Align2::LEFT_TOP,
+        format!("{} ms", scale_ms),
+        egui::FontId::monospace(11f32),
+        ui.visuals().weak_text_color(),
+    );
+
+    if let Some(pointer) = response.hover_pos() {
+        let index = ((pointer.x - rect.left()) / step).round() as usize;
+        if let Some(timings) = index
+            .checked_sub(first)
+            .and_then(|i| performance.history.get(i))
+        {
+            let lines: Vec<String> = SERIES
+                .iter()
+                .enumerate()
+                .filter_map(|(i, (name, _))| {
+                    series_value(timings, i).map(|ms| format!("{}: {:.2} ms", name, ms))
+                })
+                .collect();
+            response.on_hover_text_at_pointer(lines.join("\n"));
+        }
+    }
+}
+
+/// GPU pass times, CPU timings and ray statistics of the last renders.
+pub fn ui(ui: &mut egui::Ui, settings: &Arc<Mutex<Settings>>) {
+    let Ok(settings) = settings.lock() else {
+        ui.label("Failed to acquire settings lock.");
+        return;
+    };
+    let performance = &settings.performance;
+
+    ui.heading("Performance");
+    match performance.timestamps {
+        Some(false) => {
+            ui.label(
+                "GPU timestamps are not supported by this adapter, only CPU timings are shown.",
+            );
+        }
+        Some(true) if !performance.timestamps_inside_passes => {
+            ui.label("The blit can't be timed on this adapter.");
+        }
+        _ => {}
+    }
+
+    ui.horizontal(|ui| {
+        for (name, color) in SERIES {
+            ui.colored_label(color, "■");
+            ui.label(name);
+        }
+    });
+    graph_ui(ui, performance);
+
+    let format_ms = |ms: Option<f32>| match ms {
+        Some(ms) => format!("{:.2} ms", ms),
+        None => "-".to_string(),
+    };
+    egui::Grid::new("performance_averages")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("compute");
+            ui.label(format_ms(performance.average(|t| t.compute_ms)));
+            ui.end_row();
+            ui.label("blit");
+            ui.label(format_ms(performance.average(|t| t.blit_ms)));
+            ui.end_row();
+            ui.label("prepare (CPU)");
+            ui.label(format_ms(performance.average(|t| Some(t.prepare_ms))));
+            ui.end_row();
+            ui.label("last volume upload");
+            ui.label(format_ms(performance.upload_ms));
+            ui.end_row();
+            ui.label("samples per ray");
+            ui.label(
+                performance
+                    .average(|t| Some(t.samples_per_ray))
+                    .map_or("-".to_string(), |value| format!("{:.1}", value)),
+            );
+            ui.end_row();
+            ui.label("light samples per ray");
+            ui.label(
+                performance
+                    .average(|t| Some(t.light_samples_per_ray))
+                    .map_or("-".to_string(), |value| format!("{:.1}", value)),
+            );
+            ui.end_row();
+            ui.label("early terminated rays");
+            ui.label(
+                performance
+                    .average(|t| Some(t.early_terminated_percent))
+                    .map_or("-".to_string(), |value| format!("{:.1} %", value)),
+            );
+            ui.end_row();
+        });
+    ui.label(format!(
+        "averaged over the last {} renders",
+        performance.history.len()
+    ));
+}
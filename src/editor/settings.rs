@@ -1,34 +1,756 @@
+use super::bookmarks::Bookmarks;
+use super::camera_path::CameraPath;
+use super::performance::Performance;
+use super::view::View;
 use super::FPSController;
-use glam::{Mat4, Vec3};
+use crate::aabb::Aabb;
+use crate::loader::{LoadTask, RepackTask};
+use crate::sequence::FrameSequence;
+use crate::volume_grid::{LoadOptions, VolumeData};
+use glam::{EulerRot, Mat4, Quat, Vec3};
+use std::sync::Arc;
+
+// must match MAX_CLIP_PLANES in compute.wgsl
+pub const MAX_CLIP_PLANES: usize = 4;
+// must match MAX_VOLUMES in compute.wgsl
+pub const MAX_VOLUMES: usize = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum RenderMode {
+    PhysicallyBased,
+    MaximumIntensity,
+    MinimumIntensity,
+    AverageIntensity,
+    FirstHit,
+    Isosurface,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 6] = [
+        RenderMode::PhysicallyBased,
+        RenderMode::MaximumIntensity,
+        RenderMode::MinimumIntensity,
+        RenderMode::AverageIntensity,
+        RenderMode::FirstHit,
+        RenderMode::Isosurface,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            RenderMode::PhysicallyBased => "Physically based",
+            RenderMode::MaximumIntensity => "Maximum intensity",
+            RenderMode::MinimumIntensity => "Minimum intensity",
+            RenderMode::AverageIntensity => "Average intensity",
+            RenderMode::FirstHit => "First hit",
+            RenderMode::Isosurface => "Isosurface",
+        }
+    }
+
+    // must match RENDER_MODE_* constants in compute.wgsl
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            RenderMode::PhysicallyBased => 0,
+            RenderMode::MaximumIntensity => 1,
+            RenderMode::MinimumIntensity => 2,
+            RenderMode::AverageIntensity => 3,
+            RenderMode::FirstHit => 4,
+            RenderMode::Isosurface => 5,
+        }
+    }
+
+    pub fn uses_iso_value(&self) -> bool {
+        matches!(self, RenderMode::FirstHit | RenderMode::Isosurface)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ToneMapOperator {
+    Clamp,
+    Reinhard,
+    Aces,
+    Agx,
+}
+
+impl ToneMapOperator {
+    pub const ALL: [ToneMapOperator; 4] = [
+        ToneMapOperator::Clamp,
+        ToneMapOperator::Reinhard,
+        ToneMapOperator::Aces,
+        ToneMapOperator::Agx,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ToneMapOperator::Clamp => "Clamp",
+            ToneMapOperator::Reinhard => "Reinhard",
+            ToneMapOperator::Aces => "ACES filmic",
+            ToneMapOperator::Agx => "AgX",
+        }
+    }
+
+    // must match TONE_MAP_* constants in blit.wgsl
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            ToneMapOperator::Clamp => 0,
+            ToneMapOperator::Reinhard => 1,
+            ToneMapOperator::Aces => 2,
+            ToneMapOperator::Agx => 3,
+        }
+    }
+
+    pub fn uses_white_point(&self) -> bool {
+        !matches!(self, ToneMapOperator::Clamp)
+    }
+}
+
+/// Image shown in the render pane, the beauty render or one of the AOVs.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputPass {
+    Beauty,
+    Direct,
+    Emission,
+    Transmittance,
+    FirstDepth,
+    WeightedDepth,
+    DensityIntegral,
+}
+
+impl OutputPass {
+    pub const ALL: [OutputPass; 7] = [
+        OutputPass::Beauty,
+        OutputPass::Direct,
+        OutputPass::Emission,
+        OutputPass::Transmittance,
+        OutputPass::FirstDepth,
+        OutputPass::WeightedDepth,
+        OutputPass::DensityIntegral,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OutputPass::Beauty => "Beauty",
+            OutputPass::Direct => "Direct in-scattering",
+            OutputPass::Emission => "Emission",
+            OutputPass::Transmittance => "Transmittance",
+            OutputPass::FirstDepth => "First scatter depth",
+            OutputPass::WeightedDepth => "Weighted depth",
+            OutputPass::DensityIntegral => "Density integral",
+        }
+    }
+
+    // must match PASS_* constants in blit.wgsl
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            OutputPass::Beauty => 0,
+            OutputPass::Direct => 1,
+            OutputPass::Emission => 2,
+            OutputPass::Transmittance => 3,
+            OutputPass::FirstDepth => 4,
+            OutputPass::WeightedDepth => 5,
+            OutputPass::DensityIntegral => 6,
+        }
+    }
+}
+
+/// Mapping of the HDR render to display values.
+#[derive(Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    /// In stops.
+    pub exposure: f32,
+    /// Linear value shown as white.
+    pub white_point: f32,
+}
+
+impl ToneMapping {
+    pub fn default() -> Self {
+        Self {
+            operator: ToneMapOperator::Clamp,
+            exposure: 0f32,
+            white_point: 4f32,
+        }
+    }
+}
+
+/// Step sizes of the physically based mode that follow the scene instead of staying
+/// fixed along the ray.
+#[derive(Clone, Copy, PartialEq)]
+pub struct AdaptiveStep {
+    pub enabled: bool,
+    /// Largest step as a multiple of the ray marching step, taken in empty and thin regions.
+    pub max_scale: f32,
+    /// Smallest step in pixel footprints, so that distant parts aren't sampled finer
+    /// than the pixels can show.
+    pub footprint: f32,
+    /// Step of the rays towards the light in voxels.
+    pub light_step: f32,
+    /// The light step grows to reach the end of the volume within this many samples.
+    pub max_light_steps: u32,
+    /// Offsets the first sample of every pixel randomly, trading banding for noise.
+    pub jitter: bool,
+}
+
+impl AdaptiveStep {
+    pub fn default() -> Self {
+        Self {
+            enabled: false,
+            max_scale: 8f32,
+            footprint: 1f32,
+            light_step: 6f32,
+            max_light_steps: 32,
+            jitter: true,
+        }
+    }
+}
+
+/// Removes the half-space the normal points to. The offset moves the plane along its
+/// normal: -1 and 1 touch the opposite corners of the volume bbox, 0 passes through its center.
+#[derive(Clone, Copy, PartialEq)]
+pub struct ClipPlane {
+    pub enabled: bool,
+    pub normal: Vec3,
+    pub offset: f32,
+}
+
+impl ClipPlane {
+    fn new(normal: Vec3) -> Self {
+        Self {
+            enabled: false,
+            normal,
+            offset: 1f32,
+        }
+    }
+
+    pub fn unit_normal(&self) -> Vec3 {
+        self.normal.try_normalize().unwrap_or(Vec3::X)
+    }
+
+    /// Point on the plane in volume space.
+    pub fn origin(&self, bbox: &Aabb) -> Vec3 {
+        let normal = self.unit_normal();
+        let support = bbox.half_extent().dot(normal.abs());
+        bbox.center() + normal * self.offset * support
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Material {
+    pub absorption: f32,
+    pub scattering: f32,
+    pub g: f32,
+    /// Radiance emitted per unit density, in linear color.
+    pub emission_color: Vec3,
+    pub emission: f32,
+}
+
+impl Material {
+    pub fn default() -> Self {
+        Self {
+            absorption: 0.01,
+            scattering: 0.08,
+            g: 0.1,
+            emission_color: Vec3::new(1f32, 0.5f32, 0.2f32),
+            emission: 0f32,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OverlayMode {
+    Off,
+    Glyphs,
+    Streamlines,
+}
+
+impl OverlayMode {
+    pub const ALL: [OverlayMode; 3] = [
+        OverlayMode::Off,
+        OverlayMode::Glyphs,
+        OverlayMode::Streamlines,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OverlayMode::Off => "Off",
+            OverlayMode::Glyphs => "Glyphs",
+            OverlayMode::Streamlines => "Streamlines",
+        }
+    }
+}
+
+/// Drawing of the velocity grids over the render pane.
+#[derive(Clone, Copy, PartialEq)]
+pub struct VelocityOverlay {
+    pub mode: OverlayMode,
+    /// Voxels between two glyphs along each axis.
+    pub spacing: u32,
+    /// Length of the largest glyph in glyph spacings.
+    pub scale: f32,
+    /// Streamline seeds along each axis of the grid.
+    pub seeds: u32,
+    /// Integration steps of a streamline, each one voxel long.
+    pub steps: u32,
+}
+
+impl VelocityOverlay {
+    pub fn default() -> Self {
+        Self {
+            mode: OverlayMode::Off,
+            spacing: 8,
+            scale: 1f32,
+            seeds: 6,
+            steps: 64,
+        }
+    }
+}
+
+/// Playback state of the animated volumes.
+pub struct Timeline {
+    pub frame: u32,
+    pub start: u32,
+    pub end: u32,
+    pub playing: bool,
+    pub fps: f32,
+    /// Time since the last frame change.
+    elapsed: f32,
+}
+
+impl Timeline {
+    pub fn default() -> Self {
+        Self {
+            frame: 0,
+            start: 0,
+            end: 0,
+            playing: false,
+            fps: 24f32,
+            elapsed: 0f32,
+        }
+    }
+
+    /// Frame `offset` frames after the given one, wrapping around the range.
+    pub fn next_frame(&self, frame: u32, offset: u32) -> u32 {
+        let length = self.end - self.start + 1;
+        self.start + (frame.saturating_sub(self.start) + offset) % length
+    }
+
+    /// Moves the playhead forward by the time passed since the last update.
+    pub fn advance(&mut self, dt: f32) {
+        if !self.playing || self.fps <= 0f32 {
+            self.elapsed = 0f32;
+            return;
+        }
+        self.elapsed += dt;
+        let frame_time = 1f32 / self.fps;
+        if self.elapsed >= frame_time {
+            // drop frames we are too late for instead of catching up
+            let frames = (self.elapsed / frame_time) as u32;
+            self.elapsed -= frames as f32 * frame_time;
+            self.frame = self.next_frame(self.frame, frames);
+        }
+    }
+}
+
+/// A loaded grid placed in the scene.
+pub struct VolumeInstance {
+    pub data: Arc<VolumeData>,
+    /// Set when the file is a frame of a numbered sequence, `data` then holds the current frame.
+    pub sequence: Option<Arc<FrameSequence>>,
+    pub visible: bool,
+    pub translation: Vec3,
+    /// Euler angles in degrees, applied in XYZ order.
+    pub rotation: Vec3,
+    pub scale: f32,
+    pub material: Material,
+}
+
+impl VolumeInstance {
+    pub fn new(data: Arc<VolumeData>) -> Self {
+        Self {
+            sequence: FrameSequence::detect(&data.path).map(Arc::new),
+            data,
+            visible: true,
+            translation: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: 1f32,
+            material: Material::default(),
+        }
+    }
+
+    pub fn object_to_world(&self) -> Mat4 {
+        let rotation = Quat::from_euler(
+            EulerRot::XYZ,
+            self.rotation.x.to_radians(),
+            self.rotation.y.to_radians(),
+            self.rotation.z.to_radians(),
+        );
+        Mat4::from_scale_rotation_translation(Vec3::splat(self.scale), rotation, self.translation)
+    }
+
+    pub fn world_bbox(&self) -> Aabb {
+        self.data.grid.bbox().transformed(&self.object_to_world())
+    }
+}
 
 pub struct Settings {
     pub background_color: Vec3,
+    /// Leaves the background out and renders premultiplied color with coverage as alpha.
+    pub transparent_background: bool,
     pub light_color: Vec3,
     pub lightness: f32,
-    pub g: f32,
-    pub absorption: f32,
-    pub scattering: f32,
     pub _spp: u32,
     pub ray_marching_step: f32,
-    pub picked_path: Option<String>,
+    pub adaptive_step: AdaptiveStep,
+    /// Lights the physically based mode from a precomputed transmittance volume.
+    pub light_cache: bool,
+    pub tone_mapping: ToneMapping,
+    pub display_pass: OutputPass,
+    pub render_mode: RenderMode,
+    pub iso_value: f32,
+    /// Remap of the stored densities applied by the shader.
+    pub density_scale: f32,
+    pub density_gamma: f32,
+    pub density_contrast: f32,
+    pub density_invert: bool,
+    pub clip_planes: [ClipPlane; MAX_CLIP_PLANES],
+    pub cap_clip_planes: bool,
+    pub volumes: Vec<VolumeInstance>,
+    pub selected_volume: Option<usize>,
+    /// Bumped whenever volumes are added or removed, so that the weights get re-uploaded.
+    pub volumes_generation: u64,
+    /// Last `volumes_generation` the renderer uploaded to the GPU.
+    pub uploaded_generation: u64,
+    /// Files being loaded in the background.
+    pub loads: Vec<LoadTask>,
+    /// Applied to every loaded grid, changing them repacks the loaded volumes.
+    pub load_options: LoadOptions,
+    /// Volumes being repacked with `load_options` in the background.
+    pub repack: Option<RepackTask>,
+    /// Largest weights buffer the adapter can bind, set once the device exists.
+    pub weights_limit: u64,
+    pub timeline: Timeline,
+    pub camera_path: CameraPath,
+    pub bookmarks: Bookmarks,
+    pub velocity_overlay: VelocityOverlay,
     pub matrix: Mat4,
     pub fps_ctrl: FPSController,
+    pub performance: Performance,
 }
 
 impl Settings {
     pub fn default() -> Self {
         Self {
-            background_color: Vec3::new(0.7f32, 0.7f32, 0.9f32),
+            background_color: Vec3::new(0.448f32, 0.448f32, 0.787f32),
+            transparent_background: false,
             light_color: Vec3::new(1.0, 0.9, 0.9),
             lightness: 2.5f32,
-            g: 0.1,
-            absorption: 0.01,
-            scattering: 0.08,
             ray_marching_step: 3f32,
+            adaptive_step: AdaptiveStep::default(),
+            light_cache: false,
+            tone_mapping: ToneMapping::default(),
+            display_pass: OutputPass::Beauty,
+            render_mode: RenderMode::PhysicallyBased,
+            iso_value: 0.1,
+            density_scale: 1f32,
+            density_gamma: 1f32,
+            density_contrast: 1f32,
+            density_invert: false,
+            clip_planes: [
+                ClipPlane::new(Vec3::X),
+                ClipPlane::new(Vec3::Y),
+                ClipPlane::new(Vec3::Z),
+                ClipPlane::new(Vec3::new(1.0, 1.0, 0.0)),
+            ],
+            cap_clip_planes: false,
+            volumes: Vec::new(),
+            selected_volume: None,
+            volumes_generation: 0,
+            uploaded_generation: 0,
+            loads: Vec::new(),
+            load_options: LoadOptions::default(),
+            repack: None,
+            weights_limit: u64::MAX,
             _spp: 1u32,
-            picked_path: None,
+            timeline: Timeline::default(),
+            camera_path: CameraPath::default(),
+            bookmarks: Bookmarks::default(),
+            velocity_overlay: VelocityOverlay::default(),
             matrix: Mat4::IDENTITY,
             fps_ctrl: FPSController::default(),
+            performance: Performance::default(),
+        }
+    }
+
+    pub fn add_volume(&mut self, data: Arc<VolumeData>) {
+        let volume = VolumeInstance::new(data);
+        if self.volumes.is_empty() {
+            // every frame of a sequence shares the bookmarks of the first one
+            let path = match &volume.sequence {
+                Some(sequence) => sequence.path(sequence.first_frame()),
+                None => volume.data.path.clone(),
+            };
+            self.bookmarks = Bookmarks::load_for(&path);
+        }
+        if let Some(sequence) = &volume.sequence {
+            let has_sequences = self.has_sequences();
+            let timeline = &mut self.timeline;
+            if has_sequences {
+                timeline.start = timeline.start.min(sequence.first_frame());
+                timeline.end = timeline.end.max(sequence.last_frame());
+            } else {
+                timeline.start = sequence.first_frame();
+                timeline.end = sequence.last_frame();
+                timeline.frame = timeline.start;
+            }
         }
+        self.volumes.push(volume);
+        self.selected_volume = Some(self.volumes.len() - 1);
+        self.volumes_generation += 1;
+    }
+
+    /// Starts loading a VDB file in the background, it is added to the scene once read.
+    pub fn open_volume(&mut self, path: &str) {
+        self.loads.push(LoadTask::spawn(path, self.load_options));
+    }
+
+    /// Checks that the weights of all volumes packed with `options`, plus `added_bytes` of
+    /// a new volume, fit into one buffer binding.
+    pub fn check_weights_size(
+        &self,
+        options: &LoadOptions,
+        added_bytes: usize,
+    ) -> Result<(), String> {
+        let bytes = self
+            .volumes
+            .iter()
+            .map(|volume| volume.data.packed_bytes_with(options))
+            .sum::<usize>()
+            + added_bytes;
+        if bytes as u64 > self.weights_limit {
+            let mib = |bytes: f64| bytes / (1u64 << 20) as f64;
+            return Err(format!(
+                "the volumes need {:.1} MiB of densities, the GPU binds at most {:.1} MiB",
+                mib(bytes as f64),
+                mib(self.weights_limit as f64)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Starts repacking the loaded volumes in the background, refused if their weights
+    /// would no longer fit. Replaces a repack that is still running.
+    pub fn set_load_options(&mut self, options: LoadOptions) -> Result<(), String> {
+        if !self.load_options.repacks(&options) {
+            self.load_options = options;
+            return Ok(());
+        }
+        self.check_weights_size(&options, 0)?;
+        self.load_options = options;
+        let volumes = self
+            .volumes
+            .iter()
+            .map(|volume| volume.data.clone())
+            .collect();
+        self.repack = Some(RepackTask::spawn(volumes, options));
+        Ok(())
+    }
+
+    /// Swaps in the repacked volumes once they are done. Volumes whose data changed in the
+    /// meantime, like sequences switching frames, keep their new data.
+    pub fn poll_repack(&mut self) {
+        let Some(result) = self.repack.as_mut().and_then(|repack| repack.try_take()) else {
+            return;
+        };
+        self.repack = None;
+        match result {
+            Ok(repacked) => {
+                for (original, data) in repacked {
+                    let volume = self
+                        .volumes
+                        .iter_mut()
+                        .find(|volume| Arc::ptr_eq(&volume.data, &original));
+                    if let Some(volume) = volume {
+                        volume.data = Arc::new(data);
+                    }
+                }
+                self.volumes_generation += 1;
+            }
+            Err(err) => log::error!("Failed to repack the volumes: {}", err),
+        }
+    }
+
+    /// Adds finished loads to the scene and forgets the uploaded ones.
+    /// Returns true if a volume was added.
+    pub fn poll_loads(&mut self) -> bool {
+        let mut added = false;
+        let mut index = 0;
+        while index < self.loads.len() {
+            if self.loads[index].is_uploaded(self.uploaded_generation) {
+                self.loads.remove(index);
+                continue;
+            }
+            match self.loads[index].try_take() {
+                Some(Ok(data)) => {
+                    let options = self.load_options;
+                    if let Err(err) = self.check_weights_size(&options, data.packed_bytes()) {
+                        log::error!("Failed to load {}: {}", self.loads[index].name, err);
+                        self.loads.remove(index);
+                        continue;
+                    }
+                    self.add_volume(Arc::new(data));
+                    let generation = self.volumes_generation;
+                    self.loads[index].wait_for_upload(generation);
+                    added = true;
+                }
+                Some(Err(err)) => {
+                    log::error!("Failed to load {}: {}", self.loads[index].name, err);
+                    self.loads.remove(index);
+                    continue;
+                }
+                None => {}
+            }
+            index += 1;
+        }
+        added
+    }
+
+    /// Replaces the grid of a volume, used to switch sequence frames.
+    pub fn set_volume_data(&mut self, index: usize, data: Arc<VolumeData>) {
+        if let Some(volume) = self.volumes.get_mut(index) {
+            volume.data = data;
+            self.volumes_generation += 1;
+        }
+    }
+
+    pub fn has_sequences(&self) -> bool {
+        self.volumes.iter().any(|volume| volume.sequence.is_some())
+    }
+
+    /// Sequences or a camera path give the timeline something to play.
+    pub fn is_animated(&self) -> bool {
+        self.has_sequences() || !self.camera_path.is_empty()
+    }
+
+    /// Replaces the camera path with a turntable over `frames` frames from the start of the
+    /// sequences, or from frame 1.
+    pub fn set_turntable(&mut self, view: &View, frames: u32) -> Result<(), String> {
+        let start = if self.has_sequences() {
+            self.timeline.start
+        } else {
+            1
+        };
+        self.camera_path = CameraPath::turntable(&self.scene_bbox(), view, start, frames)?;
+        self.fit_timeline_to_camera_path();
+        Ok(())
+    }
+
+    /// Without sequences the timeline spans the camera path.
+    pub fn fit_timeline_to_camera_path(&mut self) {
+        if self.has_sequences() {
+            return;
+        }
+        let timeline = &mut self.timeline;
+        let (start, end) = self
+            .camera_path
+            .frame_range()
+            .unwrap_or((timeline.frame, timeline.frame));
+        timeline.start = start;
+        timeline.end = end;
+        timeline.frame = timeline.frame.clamp(start, end);
+    }
+
+    pub fn remove_volume(&mut self, index: usize) {
+        self.volumes.remove(index);
+        self.selected_volume = match self.selected_volume {
+            Some(selected) if selected > index => Some(selected - 1),
+            Some(selected) if selected == index => None,
+            selected => selected,
+        };
+        self.volumes_generation += 1;
+    }
+
+    /// Union of the bounds of all visible volumes.
+    pub fn scene_bbox(&self) -> Aabb {
+        self.volumes
+            .iter()
+            .filter(|volume| volume.visible)
+            .map(|volume| volume.world_bbox())
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or(Aabb::new([0f32; 4], [0f32; 4]))
+    }
+
+    /// Human readable description of what the renderer is set up to draw, saved along images.
+    pub fn summary(&self) -> String {
+        let mut lines = vec![
+            format!("render mode: {}", self.render_mode.label()),
+            format!("ray marching step: {}", self.ray_marching_step),
+            format!("light color: {:?}", self.light_color.to_array()),
+            format!("lightness: {}", self.lightness),
+            format!("background color: {:?}", self.background_color.to_array()),
+            format!("transparent background: {}", self.transparent_background),
+            format!(
+                "tone mapping: {}, exposure {}, white point {}",
+                self.tone_mapping.operator.label(),
+                self.tone_mapping.exposure,
+                self.tone_mapping.white_point
+            ),
+            format!(
+                "density: multiplier {}, gamma {}, contrast {}, invert {}",
+                self.density_scale, self.density_gamma, self.density_contrast, self.density_invert
+            ),
+            format!("camera to world: {:?}", self.matrix.to_cols_array()),
+        ];
+        if self.render_mode.uses_iso_value() {
+            lines.push(format!("iso value: {}", self.iso_value));
+        }
+        if self.light_cache && self.render_mode == RenderMode::PhysicallyBased {
+            lines.push("light cache: on".to_string());
+        }
+        let adaptive = &self.adaptive_step;
+        if adaptive.enabled && self.render_mode == RenderMode::PhysicallyBased {
+            lines.push(format!(
+                "adaptive step: max scale {}, footprint {}, light step {}, \
+                 max light steps {}, jitter {}",
+                adaptive.max_scale,
+                adaptive.footprint,
+                adaptive.light_step,
+                adaptive.max_light_steps,
+                adaptive.jitter
+            ));
+        }
+        for (i, plane) in self.clip_planes.iter().enumerate() {
+            if plane.enabled {
+                lines.push(format!(
+                    "clip plane {}: normal {:?}, offset {}",
+                    i,
+                    plane.normal.to_array(),
+                    plane.offset
+                ));
+            }
+        }
+        for volume in self.volumes.iter().filter(|volume| volume.visible) {
+            let material = &volume.material;
+            lines.push(format!(
+                "volume {}: translation {:?}, rotation {:?}, scale {}, \
+                 absorption {}, scattering {}, g {}, emission {} {:?}",
+                volume.data.path,
+                volume.translation.to_array(),
+                volume.rotation.to_array(),
+                volume.scale,
+                material.absorption,
+                material.scattering,
+                material.g,
+                material.emission,
+                material.emission_color.to_array()
+            ));
+        }
+        lines.join("\n")
+    }
+
+    /// Volume inspected by the slice and grid info panes, the first one if none is selected.
+    pub fn selected_volume_data(&self) -> Option<Arc<VolumeData>> {
+        self.selected_volume
+            .and_then(|index| self.volumes.get(index))
+            .or(self.volumes.first())
+            .map(|volume| volume.data.clone())
     }
 }
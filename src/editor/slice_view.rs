@@ -0,0 +1,244 @@
+use super::settings::Settings;
+use crate::volume_grid::VolumeGridDense;
+use std::sync::{Arc, Mutex};
+
+const MIN_ZOOM: f32 = 0.1f32;
+const MAX_ZOOM: f32 = 64f32;
+const VIRIDIS: [[f32; 3]; 5] = [
+    [0.267, 0.005, 0.329],
+    [0.229, 0.322, 0.546],
+    [0.128, 0.567, 0.551],
+    [0.369, 0.789, 0.383],
+    [0.993, 0.906, 0.144],
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SliceAxis {
+    X,
+    Y,
+    Z,
+}
+
+impl SliceAxis {
+    /// Grid axes shown as image columns and rows, and the sliced axis.
+    fn axes(&self) -> (usize, usize, usize) {
+        match self {
+            SliceAxis::X => (2, 1, 0),
+            SliceAxis::Y => (0, 2, 1),
+            SliceAxis::Z => (0, 1, 2),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Colormap {
+    Grayscale,
+    Heat,
+    Viridis,
+}
+
+impl Colormap {
+    fn map(&self, value: f32) -> egui::Color32 {
+        let v = value.clamp(0f32, 1f32);
+        let rgb = match self {
+            Colormap::Grayscale => [v, v, v],
+            Colormap::Heat => [
+                (v * 3f32).clamp(0f32, 1f32),
+                (v * 3f32 - 1f32).clamp(0f32, 1f32),
+                (v * 3f32 - 2f32).clamp(0f32, 1f32),
+            ],
+            Colormap::Viridis => {
+                let scaled = v * (VIRIDIS.len() - 1) as f32;
+                let i = (scaled.floor() as usize).min(VIRIDIS.len() - 2);
+                let t = scaled - i as f32;
+                let (a, b) = (VIRIDIS[i], VIRIDIS[i + 1]);
+                [
+                    a[0] + (b[0] - a[0]) * t,
+                    a[1] + (b[1] - a[1]) * t,
+                    a[2] + (b[2] - a[2]) * t,
+                ]
+            }
+        };
+        egui::Color32::from_rgb(
+            (rgb[0] * 255f32) as u8,
+            (rgb[1] * 255f32) as u8,
+            (rgb[2] * 255f32) as u8,
+        )
+    }
+}
+
+pub struct SliceView {
+    settings: Arc<Mutex<Settings>>,
+    axis: SliceAxis,
+    index: usize,
+    colormap: Colormap,
+    zoom: f32,
+    pan: egui::Vec2,
+    texture: Option<egui::TextureHandle>,
+    texture_key: Option<(SliceAxis, usize, Colormap, usize)>,
+}
+
+impl SliceView {
+    pub fn new(settings: Arc<Mutex<Settings>>) -> Self {
+        Self {
+            settings,
+            axis: SliceAxis::Z,
+            index: 0,
+            colormap: Colormap::Grayscale,
+            zoom: 1f32,
+            pan: egui::Vec2::ZERO,
+            texture: None,
+            texture_key: None,
+        }
+    }
+
+    fn slice_image(&self, volume: &VolumeGridDense) -> egui::ColorImage {
+        let (col_axis, row_axis, slice_axis) = self.axis.axes();
+        let width = volume.size[col_axis];
+        let height = volume.size[row_axis];
+        let max_value = volume.statistics.max_value;
+        let scale = if max_value > 0f32 {
+            max_value.recip()
+        } else {
+            0f32
+        };
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in 0..height {
+            for col in 0..width {
+                let mut pos = [0usize; 3];
+                pos[col_axis] = col;
+                // flip rows so that the up axis points up on screen
+                pos[row_axis] = height - 1 - row;
+                pos[slice_axis] = self.index;
+                let value = volume.get(pos[0], pos[1], pos[2]).unwrap_or(0f32);
+                pixels.push(self.colormap.map(value * scale));
+            }
+        }
+
+        egui::ColorImage {
+            size: [width, height],
+            pixels,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let volume = self
+            .settings
+            .lock()
+            .ok()
+            .and_then(|settings| settings.selected_volume_data());
+        let Some(data) = volume else {
+            ui.label("No volume loaded.");
+            return;
+        };
+        let volume = &data.dense;
+
+        let (col_axis, row_axis, slice_axis) = self.axis.axes();
+        let slice_count = volume.size[slice_axis];
+        self.index = self.index.min(slice_count.saturating_sub(1));
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.axis, SliceAxis::X, "X");
+            ui.selectable_value(&mut self.axis, SliceAxis::Y, "Y");
+            ui.selectable_value(&mut self.axis, SliceAxis::Z, "Z");
+            egui::ComboBox::from_id_salt("slice_colormap")
+                .selected_text(match self.colormap {
+                    Colormap::Grayscale => "grayscale",
+                    Colormap::Heat => "heat",
+                    Colormap::Viridis => "viridis",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.colormap, Colormap::Grayscale, "grayscale");
+                    ui.selectable_value(&mut self.colormap, Colormap::Heat, "heat");
+                    ui.selectable_value(&mut self.colormap, Colormap::Viridis, "viridis");
+                });
+            if ui.button("reset view").clicked() {
+                self.zoom = 1f32;
+                self.pan = egui::Vec2::ZERO;
+            }
+        });
+        ui.add(egui::Slider::new(&mut self.index, 0..=slice_count.saturating_sub(1)).text("slice"));
+
+        // the volume pointer is part of the key so that a newly loaded grid is picked up
+        let key = (
+            self.axis,
+            self.index,
+            self.colormap,
+            Arc::as_ptr(&data) as usize,
+        );
+        if self.texture_key != Some(key) {
+            let image = self.slice_image(volume);
+            match &mut self.texture {
+                Some(texture) => texture.set(image, egui::TextureOptions::NEAREST),
+                None => {
+                    self.texture = Some(ui.ctx().load_texture(
+                        "slice_view",
+                        image,
+                        egui::TextureOptions::NEAREST,
+                    ))
+                }
+            }
+            self.texture_key = Some(key);
+        }
+        let Some(texture) = &self.texture else {
+            return;
+        };
+
+        // leave room for the pane drag button below
+        let footer = egui::vec2(
+            0f32,
+            ui.spacing().interact_size.y + ui.spacing().item_spacing.y,
+        );
+        let (rect, response) = ui.allocate_exact_size(
+            (ui.available_size() - footer).max(egui::Vec2::ZERO),
+            egui::Sense::click_and_drag(),
+        );
+
+        if response.hovered() {
+            let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+            if scroll != 0f32 {
+                self.zoom = (self.zoom * (scroll * 0.005f32).exp()).clamp(MIN_ZOOM, MAX_ZOOM);
+            }
+        }
+        if response.dragged() {
+            self.pan += response.drag_delta();
+        }
+
+        let image_size = texture.size_vec2();
+        let fit = (rect.width() / image_size.x).min(rect.height() / image_size.y);
+        let pixel_size = fit * self.zoom;
+        let image_rect =
+            egui::Rect::from_center_size(rect.center() + self.pan, image_size * pixel_size);
+
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0f32, ui.visuals().extreme_bg_color);
+        painter.image(
+            texture.id(),
+            image_rect,
+            egui::Rect::from_min_max(egui::pos2(0f32, 0f32), egui::pos2(1f32, 1f32)),
+            egui::Color32::WHITE,
+        );
+
+        if let Some(pointer) = response.hover_pos() {
+            let local = (pointer - image_rect.min) / pixel_size;
+            if local.x >= 0f32 && local.y >= 0f32 {
+                let col = local.x as usize;
+                let row = local.y as usize;
+                if col < volume.size[col_axis] && row < volume.size[row_axis] {
+                    let mut pos = [0usize; 3];
+                    pos[col_axis] = col;
+                    pos[row_axis] = volume.size[row_axis] - 1 - row;
+                    pos[slice_axis] = self.index;
+                    if let Some(value) = volume.get(pos[0], pos[1], pos[2]) {
+                        let index = volume.to_index_space(pos[0], pos[1], pos[2]);
+                        response.on_hover_text_at_pointer(format!(
+                            "[{}, {}, {}] = {:.5}",
+                            index[0], index[1], index[2], value
+                        ));
+                    }
+                }
+            }
+        }
+    }
+}
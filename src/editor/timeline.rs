@@ -0,0 +1,51 @@
+use super::settings::Settings;
+use std::sync::{Arc, Mutex};
+
+pub fn ui(ui: &mut egui::Ui, settings: &Arc<Mutex<Settings>>) {
+    let Ok(mut settings) = settings.lock() else {
+        ui.label("Failed to acquire settings lock.");
+        return;
+    };
+
+    ui.heading("Timeline");
+    if !settings.is_animated() {
+        ui.label(
+            "Open a numbered VDB sequence (e.g. smoke.0001.vdb) or add camera keyframes to animate.",
+        );
+        return;
+    }
+
+    let has_sequences = settings.has_sequences();
+    let timeline = &mut settings.timeline;
+    ui.horizontal(|ui| {
+        let label = if timeline.playing { "⏸" } else { "▶" };
+        if ui.button(label).clicked() {
+            timeline.playing = !timeline.playing;
+        }
+        if ui.button("⏮").clicked() {
+            timeline.frame = timeline.start;
+        }
+        ui.add(
+            egui::DragValue::new(&mut timeline.fps)
+                .speed(0.1)
+                .range(1.0..=120.0)
+                .suffix(" fps"),
+        );
+    });
+
+    if !has_sequences {
+        // camera animation alone has no frames on disk to bound it
+        ui.horizontal(|ui| {
+            ui.label("range");
+            ui.add(egui::DragValue::new(&mut timeline.start).range(0..=timeline.end));
+            ui.add(egui::DragValue::new(&mut timeline.end).range(timeline.start..=u32::MAX));
+        });
+    }
+
+    let range = timeline.start..=timeline.end;
+    let response = ui.add(egui::Slider::new(&mut timeline.frame, range).text("frame"));
+    // scrubbing pauses playback
+    if response.dragged() {
+        timeline.playing = false;
+    }
+}
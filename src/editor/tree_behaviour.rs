@@ -1,12 +1,27 @@
+use super::camera_animation;
+use super::clip_gizmo;
+use super::grid_info;
+use super::load_progress;
+use super::outliner;
+use super::performance;
 use super::render_view::RenderViewCallback;
-use super::settings::Settings;
+use super::settings::{OutputPass, OverlayMode, RenderMode, Settings, ToneMapOperator};
+use super::slice_view::SliceView;
+use super::timeline;
+use super::velocity_overlay;
 use crate::SCREEN_SIZE;
 use std::sync::{Arc, Mutex};
 pub struct TreeBehavior {}
 
 enum PaneType {
     Settings(Arc<Mutex<Settings>>),
-    Render(()),
+    Render(Arc<Mutex<Settings>>),
+    Slice(SliceView),
+    GridInfo(Arc<Mutex<Settings>>),
+    Outliner(Arc<Mutex<Settings>>),
+    Timeline(Arc<Mutex<Settings>>),
+    CameraAnimation(Arc<Mutex<Settings>>),
+    Performance(Arc<Mutex<Settings>>),
 }
 
 pub struct Pane {
@@ -17,10 +32,10 @@ pub struct Pane {
 impl TreeBehavior {
     pub fn create_tree(settings: Arc<Mutex<Settings>>) -> egui_tiles::Tree<Pane> {
         let mut next_view_nr = 0;
-        let gen_pane = || {
+        let mut gen_pane = || {
             let pane = Pane {
                 nr: next_view_nr,
-                kind: PaneType::Settings(settings),
+                kind: PaneType::Settings(settings.clone()),
             };
             next_view_nr += 1;
             pane
@@ -32,12 +47,48 @@ impl TreeBehavior {
 
         let render_pane = Pane {
             nr: 0,
-            kind: PaneType::Render(()),
+            kind: PaneType::Render(settings.clone()),
         };
         tabs.push(tiles.insert_pane(render_pane));
 
         tabs.push(tiles.insert_pane(gen_pane()));
 
+        let slice_pane = Pane {
+            nr: 2,
+            kind: PaneType::Slice(SliceView::new(settings.clone())),
+        };
+        tabs.push(tiles.insert_pane(slice_pane));
+
+        let grid_info_pane = Pane {
+            nr: 3,
+            kind: PaneType::GridInfo(settings.clone()),
+        };
+        tabs.push(tiles.insert_pane(grid_info_pane));
+
+        let outliner_pane = Pane {
+            nr: 4,
+            kind: PaneType::Outliner(settings.clone()),
+        };
+        tabs.push(tiles.insert_pane(outliner_pane));
+
+        let timeline_pane = Pane {
+            nr: 5,
+            kind: PaneType::Timeline(settings.clone()),
+        };
+        tabs.push(tiles.insert_pane(timeline_pane));
+
+        let camera_animation_pane = Pane {
+            nr: 6,
+            kind: PaneType::CameraAnimation(settings.clone()),
+        };
+        tabs.push(tiles.insert_pane(camera_animation_pane));
+
+        let performance_pane = Pane {
+            nr: 7,
+            kind: PaneType::Performance(settings.clone()),
+        };
+        tabs.push(tiles.insert_pane(performance_pane));
+
         // let root = tiles.insert_tab_tile(tabs);
         let root = tiles.insert_horizontal_tile(tabs);
 
@@ -69,7 +120,7 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior {
         _tile_id: egui_tiles::TileId,
         pane: &mut Pane,
     ) -> egui_tiles::UiResponse {
-        match &pane.kind {
+        match &mut pane.kind {
             PaneType::Settings(settings) => {
                 ui.label("Settings.".to_string());
                 // let color = settings.as_mut_slice();
@@ -78,17 +129,26 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior {
                 // }
                 // Acquire a lock to modify settings
                 if let Ok(mut settings) = settings.lock() {
+                    egui::ComboBox::from_label("render mode")
+                        .selected_text(settings.render_mode.label())
+                        .show_ui(ui, |ui| {
+                            for mode in RenderMode::ALL {
+                                ui.selectable_value(&mut settings.render_mode, mode, mode.label());
+                            }
+                        });
+                    if settings.render_mode.uses_iso_value() {
+                        ui.add(
+                            egui::Slider::new(&mut settings.iso_value, 0.0..=1.0).text("iso value"),
+                        );
+                    }
                     ui.color_edit_button_rgb(settings.background_color.as_mut());
                     ui.label("background color");
+                    ui.checkbox(
+                        &mut settings.transparent_background,
+                        "transparent background",
+                    );
                     ui.color_edit_button_rgb(settings.light_color.as_mut());
                     ui.label("light color");
-                    ui.add(egui::Slider::new(&mut settings.g, -1.0..=1.0).text("g"));
-                    ui.add(
-                        egui::Slider::new(&mut settings.absorption, 0.0..=0.1).text("absorption"),
-                    );
-                    ui.add(
-                        egui::Slider::new(&mut settings.scattering, 0.0..=0.5).text("scattering"),
-                    );
                     ui.add(
                         egui::Slider::new(&mut settings.lightness, 1.0..=20.0).text("lightness"),
                     );
@@ -96,13 +156,147 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior {
                         egui::Slider::new(&mut settings.ray_marching_step, 0.6..=10.0)
                             .text("ray marching step"),
                     );
+                    if settings.render_mode == RenderMode::PhysicallyBased {
+                        ui.checkbox(&mut settings.light_cache, "light cache")
+                            .on_hover_text(
+                                "Precompute the shadows into a volume, faster to \
+                                 navigate but blurrier",
+                            );
+                        ui.collapsing("adaptive step", |ui| {
+                            let adaptive = &mut settings.adaptive_step;
+                            ui.checkbox(&mut adaptive.enabled, "enabled");
+                            ui.add_enabled_ui(adaptive.enabled, |ui| {
+                                ui.add(
+                                    egui::Slider::new(&mut adaptive.max_scale, 1.0..=32.0)
+                                        .logarithmic(true)
+                                        .text("max step scale"),
+                                );
+                                ui.add(
+                                    egui::Slider::new(&mut adaptive.footprint, 0.0..=4.0)
+                                        .text("pixel footprint"),
+                                );
+                                ui.add(
+                                    egui::Slider::new(&mut adaptive.light_step, 0.6..=20.0)
+                                        .text("light step"),
+                                );
+                                ui.add(
+                                    egui::Slider::new(&mut adaptive.max_light_steps, 1..=256)
+                                        .logarithmic(true)
+                                        .text("max light steps"),
+                                );
+                                ui.checkbox(&mut adaptive.jitter, "jitter start");
+                            });
+                        });
+                    }
+                    ui.collapsing("tone mapping", |ui| {
+                        let tone_mapping = &mut settings.tone_mapping;
+                        egui::ComboBox::from_label("operator")
+                            .selected_text(tone_mapping.operator.label())
+                            .show_ui(ui, |ui| {
+                                for operator in ToneMapOperator::ALL {
+                                    ui.selectable_value(
+                                        &mut tone_mapping.operator,
+                                        operator,
+                                        operator.label(),
+                                    );
+                                }
+                            });
+                        ui.add(
+                            egui::Slider::new(&mut tone_mapping.exposure, -8.0..=8.0)
+                                .text("exposure (stops)"),
+                        );
+                        if tone_mapping.operator.uses_white_point() {
+                            ui.add(
+                                egui::Slider::new(&mut tone_mapping.white_point, 0.5..=64.0)
+                                    .logarithmic(true)
+                                    .text("white point"),
+                            );
+                        }
+                    });
+                    ui.collapsing("density", |ui| {
+                        ui.add(
+                            egui::Slider::new(&mut settings.density_scale, 0.0..=10.0)
+                                .logarithmic(true)
+                                .text("multiplier"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut settings.density_gamma, 0.1..=5.0)
+                                .logarithmic(true)
+                                .text("gamma"),
+                        );
+                        ui.add(
+                            egui::Slider::new(&mut settings.density_contrast, 0.1..=5.0)
+                                .logarithmic(true)
+                                .text("contrast"),
+                        );
+                        ui.checkbox(&mut settings.density_invert, "invert");
+                    });
+                    ui.collapsing("velocity overlay", |ui| {
+                        let overlay = &mut settings.velocity_overlay;
+                        egui::ComboBox::from_label("mode")
+                            .selected_text(overlay.mode.label())
+                            .show_ui(ui, |ui| {
+                                for mode in OverlayMode::ALL {
+                                    ui.selectable_value(&mut overlay.mode, mode, mode.label());
+                                }
+                            });
+                        match overlay.mode {
+                            OverlayMode::Glyphs => {
+                                ui.add(
+                                    egui::Slider::new(&mut overlay.spacing, 1..=32)
+                                        .text("spacing (voxels)"),
+                                );
+                                ui.add(
+                                    egui::Slider::new(&mut overlay.scale, 0.1..=5.0)
+                                        .logarithmic(true)
+                                        .text("scale"),
+                                );
+                            }
+                            OverlayMode::Streamlines => {
+                                ui.add(
+                                    egui::Slider::new(&mut overlay.seeds, 1..=16)
+                                        .text("seeds per axis"),
+                                );
+                                ui.add(
+                                    egui::Slider::new(&mut overlay.steps, 1..=512).text("steps"),
+                                );
+                            }
+                            OverlayMode::Off => {}
+                        }
+                    });
+                    ui.collapsing("clipping planes", |ui| {
+                        ui.checkbox(&mut settings.cap_clip_planes, "cap cut faces");
+                        for (i, plane) in settings.clip_planes.iter_mut().enumerate() {
+                            ui.checkbox(&mut plane.enabled, format!("plane {}", i));
+                            if plane.enabled {
+                                ui.horizontal(|ui| {
+                                    ui.label("normal");
+                                    ui.add(egui::DragValue::new(&mut plane.normal.x).speed(0.01));
+                                    ui.add(egui::DragValue::new(&mut plane.normal.y).speed(0.01));
+                                    ui.add(egui::DragValue::new(&mut plane.normal.z).speed(0.01));
+                                });
+                                ui.add(
+                                    egui::Slider::new(&mut plane.offset, -1.0..=1.0).text("offset"),
+                                );
+                            }
+                        }
+                    });
                     ui.heading(format!("FPS: {:.1}", settings.fps_ctrl.get_current_fps()));
                 } else {
                     ui.label("Failed to acquire settings lock.");
                 }
                 // ui.color_edit_button_rgb(color);
             }
-            PaneType::Render(_rx) => {
+            PaneType::Render(settings) => {
+                if let Ok(mut settings) = settings.lock() {
+                    egui::ComboBox::from_label("pass")
+                        .selected_text(settings.display_pass.label())
+                        .show_ui(ui, |ui| {
+                            for pass in OutputPass::ALL {
+                                ui.selectable_value(&mut settings.display_pass, pass, pass.label());
+                            }
+                        });
+                }
                 egui::Frame::canvas(ui.style()).show(ui, |ui| {
                     let width = SCREEN_SIZE[0] as f32;
                     let height = SCREEN_SIZE[1] as f32;
@@ -120,8 +314,32 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior {
                         rect,
                         RenderViewCallback {},
                     ));
+
+                    if let Ok(mut settings) = settings.lock() {
+                        velocity_overlay::show(ui, rect, &settings);
+                        clip_gizmo::show(ui, rect, &response, &mut settings);
+                        load_progress::show(ui, rect, &mut settings);
+                    }
                 });
             }
+            PaneType::Slice(slice_view) => {
+                slice_view.ui(ui);
+            }
+            PaneType::GridInfo(settings) => {
+                grid_info::ui(ui, settings);
+            }
+            PaneType::Outliner(settings) => {
+                outliner::ui(ui, settings);
+            }
+            PaneType::Timeline(settings) => {
+                timeline::ui(ui, settings);
+            }
+            PaneType::CameraAnimation(settings) => {
+                camera_animation::ui(ui, settings);
+            }
+            PaneType::Performance(settings) => {
+                performance::ui(ui, settings);
+            }
         }
 
         // You can make your pane draggable like so:
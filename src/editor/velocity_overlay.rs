@@ -0,0 +1,190 @@
+use super::clip_gizmo::project;
+use super::settings::{OverlayMode, Settings, VelocityOverlay};
+use crate::volume_grid::{VectorGridDense, VolumeData};
+use glam::{Mat4, Vec3};
+use std::sync::{Arc, Weak};
+
+/// Glyphs drawn per volume at most, the spacing grows for larger grids.
+const MAX_GLYPHS: usize = 20000;
+/// Streamline segments drawn per volume at most, fewer seeds are used above it.
+const MAX_SEGMENTS: usize = 50000;
+/// Vectors shorter than this fraction of the largest one are not drawn or followed.
+const MIN_MAGNITUDE: f32 = 0.01f32;
+const SLOW_COLOR: egui::Color32 = egui::Color32::from_rgb(137, 180, 250);
+const FAST_COLOR: egui::Color32 = egui::Color32::from_rgb(243, 139, 168);
+
+fn magnitude_color(magnitude: f32, max_magnitude: f32) -> egui::Color32 {
+    let t = (magnitude / max_magnitude).clamp(0f32, 1f32);
+    SLOW_COLOR.lerp_to_gamma(FAST_COLOR, t)
+}
+
+/// Arrows on a lattice of every `spacing`-th voxel.
+fn draw_glyphs(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    object_to_camera: &Mat4,
+    velocity: &VectorGridDense,
+    overlay: &VelocityOverlay,
+) {
+    let voxel_count = velocity.size.iter().product::<usize>();
+    let min_spacing = (voxel_count as f32 / MAX_GLYPHS as f32).cbrt().ceil() as usize;
+    let spacing = (overlay.spacing as usize).max(min_spacing).max(1);
+    let length = overlay.scale * spacing as f32 * velocity.voxel_size / velocity.max_magnitude;
+
+    for x in (spacing / 2..velocity.size[0]).step_by(spacing) {
+        for y in (spacing / 2..velocity.size[1]).step_by(spacing) {
+            for z in (spacing / 2..velocity.size[2]).step_by(spacing) {
+                let vector = velocity.get(x, y, z);
+                let magnitude = vector.length();
+                if magnitude < velocity.max_magnitude * MIN_MAGNITUDE {
+                    continue;
+                }
+                let start = velocity.voxel_center(x, y, z);
+                let (Some(tail), Some(head)) = (
+                    project(object_to_camera, rect, start),
+                    project(object_to_camera, rect, start + vector * length),
+                ) else {
+                    continue;
+                };
+                let color = magnitude_color(magnitude, velocity.max_magnitude);
+                painter.arrow(tail, head - tail, egui::Stroke::new(1f32, color));
+            }
+        }
+    }
+}
+
+/// Points of a traced streamline in object space, each with the colour of the segment
+/// ending at it.
+type Polyline = Vec<(Vec3, egui::Color32)>;
+
+/// Streamlines traced for one volume, kept until its data or the overlay settings change.
+#[derive(Clone)]
+struct StreamlineCache {
+    data: Weak<VolumeData>,
+    seeds: u32,
+    steps: u32,
+    polylines: Arc<Vec<Polyline>>,
+}
+
+/// Follows the field from seeds spread evenly over the grid, with midpoint steps of one voxel.
+/// Fewer seeds are used when the streamlines would have more than `MAX_SEGMENTS` segments.
+fn trace_streamlines(velocity: &VectorGridDense, overlay: &VelocityOverlay) -> Vec<Polyline> {
+    let min_magnitude = velocity.max_magnitude * MIN_MAGNITUDE;
+    let step = velocity.voxel_size;
+    let direction = |position: Vec3| {
+        velocity
+            .sample(position)
+            .filter(|vector| vector.length() >= min_magnitude)
+    };
+
+    let steps = overlay.steps.max(1) as usize;
+    let max_seeds = ((MAX_SEGMENTS / steps) as f32).cbrt().floor() as usize;
+    let seeds = (overlay.seeds as usize).min(max_seeds).max(1);
+    let seed_index = |i: usize, axis: usize| (2 * i + 1) * velocity.size[axis] / (2 * seeds);
+    let mut polylines = Vec::new();
+    for i in 0..seeds {
+        for j in 0..seeds {
+            for k in 0..seeds {
+                let mut position =
+                    velocity.voxel_center(seed_index(i, 0), seed_index(j, 1), seed_index(k, 2));
+                let mut polyline = vec![(position, SLOW_COLOR)];
+                for _ in 0..steps {
+                    let Some(vector) = direction(position) else {
+                        break;
+                    };
+                    let midpoint = position + vector.normalize() * step * 0.5f32;
+                    let Some(midpoint_vector) = direction(midpoint) else {
+                        break;
+                    };
+                    position += midpoint_vector.normalize() * step;
+                    let color = magnitude_color(vector.length(), velocity.max_magnitude);
+                    polyline.push((position, color));
+                }
+                if polyline.len() > 1 {
+                    polylines.push(polyline);
+                }
+            }
+        }
+    }
+    polylines
+}
+
+/// Streamlines of the volume, traced again only when its data or the overlay settings change.
+fn cached_streamlines(
+    ui: &egui::Ui,
+    id: egui::Id,
+    data: &Arc<VolumeData>,
+    velocity: &VectorGridDense,
+    overlay: &VelocityOverlay,
+) -> Arc<Vec<Polyline>> {
+    let cached = ui.data(|memory| memory.get_temp::<StreamlineCache>(id));
+    if let Some(cache) = cached {
+        if cache.data.ptr_eq(&Arc::downgrade(data))
+            && cache.seeds == overlay.seeds
+            && cache.steps == overlay.steps
+        {
+            return cache.polylines;
+        }
+    }
+    let polylines = Arc::new(trace_streamlines(velocity, overlay));
+    let cache = StreamlineCache {
+        data: Arc::downgrade(data),
+        seeds: overlay.seeds,
+        steps: overlay.steps,
+        polylines: polylines.clone(),
+    };
+    ui.data_mut(|memory| memory.insert_temp(id, cache));
+    polylines
+}
+
+fn draw_streamlines(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    object_to_camera: &Mat4,
+    polylines: &[Polyline],
+) {
+    for polyline in polylines {
+        let mut previous = project(object_to_camera, rect, polyline[0].0);
+        for &(position, color) in &polyline[1..] {
+            let current = project(object_to_camera, rect, position);
+            if let (Some(from), Some(to)) = (previous, current) {
+                painter.line_segment([from, to], egui::Stroke::new(1.5f32, color));
+            }
+            previous = current;
+        }
+    }
+}
+
+/// Draws the velocity grids of the visible volumes over the rendered image.
+pub fn show(ui: &egui::Ui, rect: egui::Rect, settings: &Settings) {
+    let overlay = settings.velocity_overlay;
+    if overlay.mode == OverlayMode::Off {
+        return;
+    }
+
+    let painter = ui.painter_at(rect);
+    let world_to_camera = settings.matrix.inverse();
+    for (index, volume) in settings.volumes.iter().enumerate() {
+        if !volume.visible {
+            continue;
+        }
+        let Some(velocity) = &volume.data.velocity else {
+            continue;
+        };
+        if velocity.max_magnitude <= 0f32 {
+            continue;
+        }
+        let object_to_camera = world_to_camera * volume.object_to_world();
+        match overlay.mode {
+            OverlayMode::Glyphs => {
+                draw_glyphs(&painter, rect, &object_to_camera, velocity, &overlay)
+            }
+            OverlayMode::Streamlines => {
+                let id = ui.id().with(("streamlines", index));
+                let polylines = cached_streamlines(ui, id, &volume.data, velocity, &overlay);
+                draw_streamlines(&painter, rect, &object_to_camera, &polylines)
+            }
+            OverlayMode::Off => {}
+        }
+    }
+}
@@ -1,5 +1,11 @@
-use glam::{Quat, Vec3};
+use crate::aabb::Aabb;
+use glam::{EulerRot, Mat4, Quat, Vec3};
 
+const FRAMING_DISTANCE: f32 = 2.5f32;
+/// Seconds the camera takes to fly to a bookmark.
+const TRANSITION_TIME: f32 = 0.6f32;
+
+#[derive(Clone, Copy, PartialEq)]
 pub struct View {
     pub rotation_x: Quat,
     pub rotation_y: Quat,
@@ -14,4 +20,111 @@ impl View {
             translation: Vec3::NEG_Z * 150f32,
         }
     }
+
+    /// Looks along +Z at the bbox center from far enough to see all of it.
+    pub fn framing(bbox: &Aabb) -> Self {
+        let radius = bbox.half_extent().length();
+        if radius <= 0f32 {
+            return Self::default();
+        }
+        Self {
+            rotation_x: Quat::IDENTITY,
+            rotation_y: Quat::IDENTITY,
+            translation: bbox.center() + Vec3::NEG_Z * radius * FRAMING_DISTANCE,
+        }
+    }
+
+    /// Splits a camera matrix back into yaw and pitch, the camera never rolls.
+    pub fn from_matrix(matrix: &Mat4) -> Self {
+        let (_, rotation, translation) = matrix.to_scale_rotation_translation();
+        let (yaw, pitch, _) = rotation.to_euler(EulerRot::YXZ);
+        Self {
+            rotation_x: Quat::from_rotation_x(pitch),
+            rotation_y: Quat::from_rotation_y(yaw),
+            translation,
+        }
+    }
+
+    /// Translation and both rotations as space separated numbers, read back by `parse`.
+    pub fn to_text(self) -> String {
+        let values: Vec<String> = self
+            .translation
+            .to_array()
+            .into_iter()
+            .chain(self.rotation_x.to_array())
+            .chain(self.rotation_y.to_array())
+            .map(|value| value.to_string())
+            .collect();
+        values.join(" ")
+    }
+
+    /// Takes the numbers written by `to_text` from the fields.
+    pub fn parse<'a>(fields: &mut impl Iterator<Item = &'a str>) -> Option<Self> {
+        let mut values = [0f32; 11];
+        for value in &mut values {
+            *value = fields.next()?.parse().ok()?;
+        }
+        Some(Self {
+            translation: Vec3::from_slice(&values[0..3]),
+            rotation_x: Quat::from_slice(&values[3..7]).normalize(),
+            rotation_y: Quat::from_slice(&values[7..11]).normalize(),
+        })
+    }
+
+    pub fn view_dir(&self) -> Vec3 {
+        (self.rotation_y * self.rotation_x * Vec3::Z).normalize()
+    }
+
+    /// Turns the camera by `angle` around the vertical axis through `center`, keeping
+    /// its pitch and distance.
+    pub fn orbit(&self, center: Vec3, angle: f32) -> Self {
+        let distance = (self.translation - center).length();
+        let mut orbit = *self;
+        orbit.rotation_y = self.rotation_y * Quat::from_rotation_y(angle);
+        orbit.translation = center - orbit.view_dir() * distance;
+        orbit
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_rotation_translation(self.rotation_y * self.rotation_x, self.translation)
+    }
+}
+
+/// Eases the camera from one view to another.
+pub struct Transition {
+    from: View,
+    to: View,
+    elapsed: f32,
+}
+
+/// Keeps the slerp on the short arc, `q` and `-q` are the same rotation.
+pub fn slerp(from: Quat, to: Quat, t: f32) -> Quat {
+    let to = if from.dot(to) < 0f32 { -to } else { to };
+    from.slerp(to, t)
+}
+
+impl Transition {
+    pub fn new(from: View, to: View) -> Self {
+        Self {
+            from,
+            to,
+            elapsed: 0f32,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= TRANSITION_TIME
+    }
+
+    /// Camera after the time passed since the last update.
+    pub fn advance(&mut self, dt: f32) -> View {
+        self.elapsed += dt;
+        let t = (self.elapsed / TRANSITION_TIME).clamp(0f32, 1f32);
+        let t = t * t * (3f32 - 2f32 * t);
+        View {
+            rotation_x: slerp(self.from.rotation_x, self.to.rotation_x, t),
+            rotation_y: slerp(self.from.rotation_y, self.to.rotation_y, t),
+            translation: self.from.translation.lerp(self.to.translation, t),
+        }
+    }
 }
@@ -0,0 +1,216 @@
+use crate::cli::Args;
+use crate::editor::camera_path::CameraPath;
+use crate::editor::settings::Settings;
+use crate::editor::view::View;
+use crate::image_file::{is_exr, save_exr, save_png};
+use crate::profiler::{GpuProfiler, TimedPass};
+use crate::renderer::{max_weights_bytes, read_texture, VolumeRenderer};
+use crate::tone_mapping::ToneMapper;
+use crate::volume_grid::VolumeData;
+use crate::{device_descriptor, SCREEN_SIZE};
+use eframe::wgpu;
+use std::sync::Arc;
+
+/// Replaces the run of `#` in the pattern with the zero padded frame number.
+/// Without one the frame number is put before the extension when rendering several frames.
+fn output_path(pattern: &str, frame: u32, single_frame: bool) -> String {
+    if let Some(start) = pattern.find('#') {
+        let width = pattern[start..].chars().take_while(|&c| c == '#').count();
+        return format!(
+            "{}{:0width$}{}",
+            &pattern[..start],
+            frame,
+            &pattern[start + width..],
+            width = width
+        );
+    }
+    if single_frame {
+        return pattern.to_string();
+    }
+    match pattern.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}.{:04}.{}", stem, frame, extension),
+        None => format!("{}.{:04}", pattern, frame),
+    }
+}
+
+/// Loads the current frame of every sequence, blocking until it is read.
+fn load_frame(settings: &mut Settings, frame: u32) -> Result<(), String> {
+    for index in 0..settings.volumes.len() {
+        let Some(sequence) = settings.volumes[index].sequence.clone() else {
+            continue;
+        };
+        let path = sequence.path(frame);
+        if settings.volumes[index].data.path != path {
+            let data = VolumeData::load(&path, &settings.load_options)
+                .map_err(|err| format!("Failed to load {}: {}", path, err))?;
+            settings.set_volume_data(index, Arc::new(data));
+        }
+    }
+    Ok(())
+}
+
+/// Settings with the inputs loaded, blocking until they are read.
+pub fn load_scene(args: &Args) -> Result<Settings, String> {
+    let mut settings = Settings::default();
+    settings.transparent_background = args.transparent;
+    settings.adaptive_step.enabled = args.adaptive;
+    settings.light_cache = args.light_cache;
+    for filename in &args.inputs {
+        let data = VolumeData::load(filename, &settings.load_options)
+            .map_err(|err| format!("Failed to load {}: {}", filename, err))?;
+        settings.add_volume(Arc::new(data));
+    }
+    Ok(settings)
+}
+
+/// Opens a device without a surface, on a software adapter if asked to.
+pub fn request_device(
+    software: bool,
+) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue), String> {
+    let instance = wgpu::Instance::default();
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        force_fallback_adapter: software,
+        compatible_surface: None,
+    }))
+    .ok_or("No suitable GPU adapter found")?;
+    log::info!("Rendering on {}", adapter.get_info().name);
+
+    let (device, queue) =
+        pollster::block_on(adapter.request_device(&device_descriptor(&adapter), None))
+            .map_err(|err| format!("Failed to create device: {}", err))?;
+    Ok((adapter, device, queue))
+}
+
+/// Renders the inputs to PNG or EXR files without opening a window.
+pub fn run(args: &Args) -> Result<(), String> {
+    let Some(output) = &args.output else {
+        return Err("no output path given".to_string());
+    };
+
+    let mut settings = load_scene(args)?;
+    let mut view = View::framing(&settings.scene_bbox());
+    if let Some(name) = &args.bookmark {
+        view = settings
+            .bookmarks
+            .list
+            .iter()
+            .find(|bookmark| &bookmark.name == name)
+            .map(|bookmark| bookmark.view)
+            .ok_or(format!("no bookmark named {}", name))?;
+    }
+    settings.matrix = view.matrix();
+    if let Some(file) = &args.camera_path {
+        settings.camera_path = CameraPath::load(file)?;
+        settings.fit_timeline_to_camera_path();
+    } else if let Some(frames) = args.turntable {
+        settings.set_turntable(&view, frames)?;
+    }
+
+    let (start, end) = match args.frames {
+        Some(range) => range,
+        None if settings.is_animated() => (settings.timeline.start, settings.timeline.end),
+        None => (settings.timeline.frame, settings.timeline.frame),
+    };
+
+    let (_, device, queue) = request_device(args.software)?;
+    settings.weights_limit = max_weights_bytes(&device.limits());
+    settings.check_weights_size(&settings.load_options, 0)?;
+
+    let mut renderer = VolumeRenderer::new(&device, SCREEN_SIZE[0], SCREEN_SIZE[1]);
+    renderer.ray_stats = false;
+    let output_format = wgpu::TextureFormat::Rgba8Unorm;
+    let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Output texture"),
+        size: renderer.result_texture.size(),
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: output_format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let tone_mapper = ToneMapper::new(&device, &renderer, output_format, true);
+    let mut profiler = GpuProfiler::new(&device, &queue);
+    for frame in start..=end {
+        load_frame(&mut settings, frame)?;
+        if let Some(view) = settings.camera_path.view_at(frame as f32) {
+            settings.matrix = view.matrix();
+        }
+        renderer.prepare(&device, &queue, &settings);
+        tone_mapper.prepare(&queue, &settings);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headless encoder"),
+        });
+        renderer.dispatch(&mut encoder, profiler.compute_timestamp_writes());
+        tone_mapper.render(
+            &mut encoder,
+            &output_view,
+            profiler.render_timestamp_writes(TimedPass::Blit),
+        );
+        queue.submit(Some(encoder.finish()));
+        let timings = profiler.read_blocking(&device, &queue, &renderer.ray_stats_buffer);
+        let format_ms = |ms: Option<f32>| ms.map_or("-".to_string(), |ms| format!("{:.2} ms", ms));
+        log::info!(
+            "Rendered frame {}: compute {}, blit {}",
+            frame,
+            format_ms(timings.pass_ms(TimedPass::Compute)),
+            format_ms(timings.pass_ms(TimedPass::Blit))
+        );
+
+        let path = output_path(output, frame, start == end);
+        if is_exr(&path) {
+            let channels = renderer.read_channels(&device, &queue);
+            save_exr(&path, renderer.width, renderer.height, channels)?;
+        } else {
+            let pixels = read_texture(&device, &queue, &output_texture);
+            save_png(&path, renderer.width, renderer.height, &pixels, &[])?;
+        }
+        log::info!("Saved {}", path);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_hashes_with_padded_frame() {
+        assert_eq!(
+            output_path("out/smoke.####.png", 7, false),
+            "out/smoke.0007.png"
+        );
+        assert_eq!(
+            output_path("out/smoke.#.png", 12, false),
+            "out/smoke.12.png"
+        );
+        assert_eq!(
+            output_path("out/smoke.##.png", 123, false),
+            "out/smoke.123.png"
+        );
+        // a single frame still gets its number when asked for one
+        assert_eq!(
+            output_path("out/smoke.###.exr", 1, true),
+            "out/smoke.001.exr"
+        );
+    }
+
+    #[test]
+    fn only_first_hash_run_is_replaced() {
+        assert_eq!(output_path("#/frame.##.png", 3, false), "3/frame.##.png");
+    }
+
+    #[test]
+    fn numbers_frames_before_the_extension() {
+        assert_eq!(
+            output_path("out/smoke.png", 42, false),
+            "out/smoke.0042.png"
+        );
+        assert_eq!(output_path("out/smoke", 42, false), "out/smoke.0042");
+        assert_eq!(output_path("out/smoke.png", 42, true), "out/smoke.png");
+    }
+}
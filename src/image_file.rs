@@ -0,0 +1,85 @@
+use half::f16;
+use std::fs::File;
+use std::io::BufWriter;
+
+/// EXR keeps the linear HDR radiance, everything else is saved as tone mapped PNG.
+pub fn is_exr(path: &str) -> bool {
+    path.rsplit_once('.')
+        .is_some_and(|(_, extension)| extension.eq_ignore_ascii_case("exr"))
+}
+
+/// Saves straight alpha RGBA8 pixels, `text` is stored as (keyword, text) metadata chunks.
+pub fn save_png(
+    path: &str,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+    text: &[(String, String)],
+) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| format!("Failed to create {}: {}", path, err))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in text {
+        encoder
+            .add_text_chunk(keyword.clone(), text.clone())
+            .map_err(|err| format!("Failed to add {} to {}: {}", keyword, path, err))?;
+    }
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels))
+        .map_err(|err| format!("Failed to write {}: {}", path, err))
+}
+
+/// One channel of an EXR image, a `layer.` prefix groups channels into layers.
+pub struct ExrChannel {
+    pub name: String,
+    pub samples: Vec<f16>,
+}
+
+/// Splits tightly packed RGBA16F rows into channels, `None` names skip a component.
+pub fn split_rgba16f(pixels: &[u8], names: [Option<&str>; 4]) -> Vec<ExrChannel> {
+    let samples: Vec<f16> = pixels
+        .chunks_exact(2)
+        .map(|bytes| f16::from_le_bytes([bytes[0], bytes[1]]))
+        .collect();
+    names
+        .iter()
+        .enumerate()
+        .filter_map(|(component, name)| {
+            Some(ExrChannel {
+                name: (*name)?.to_string(),
+                samples: samples.iter().skip(component).step_by(4).copied().collect(),
+            })
+        })
+        .collect()
+}
+
+/// Saves linear channels into a single part EXR, color stays premultiplied.
+pub fn save_exr(
+    path: &str,
+    width: u32,
+    height: u32,
+    channels: Vec<ExrChannel>,
+) -> Result<(), String> {
+    use exr::prelude::*;
+
+    let channels = AnyChannels::sort(
+        channels
+            .into_iter()
+            .map(|channel| {
+                AnyChannel::new(channel.name.as_str(), FlatSamples::F16(channel.samples))
+            })
+            .collect(),
+    );
+    let layer = Layer::new(
+        (width as usize, height as usize),
+        LayerAttributes::default(),
+        Encoding::SMALL_LOSSLESS,
+        channels,
+    );
+    Image::from_layer(layer)
+        .write()
+        .to_file(path)
+        .map_err(|err| format!("Failed to write {}: {}", path, err))
+}
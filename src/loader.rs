@@ -0,0 +1,237 @@
+use crate::volume_grid::{LoadOptions, VolumeData};
+use single_value_channel::{Receiver, Updater};
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::mpsc::{self, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LoadStage {
+    Reading,
+    Densifying,
+    Packing,
+    Uploading,
+}
+
+impl LoadStage {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LoadStage::Reading => "Reading",
+            LoadStage::Densifying => "Densifying",
+            LoadStage::Packing => "Packing",
+            LoadStage::Uploading => "Uploading",
+        }
+    }
+}
+
+pub enum LoadError {
+    Parse(vdb_rs::ParseError),
+    Cancelled,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Parse(err) => write!(f, "{}", err),
+            LoadError::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl From<vdb_rs::ParseError> for LoadError {
+    fn from(err: vdb_rs::ParseError) -> Self {
+        LoadError::Parse(err)
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Parse(err.into())
+    }
+}
+
+/// Worker side of a load: sends the current stage and its completed fraction to the UI.
+/// Loading stops at the next report once the UI dropped the task.
+pub struct LoadProgress {
+    updater: Option<Updater<(LoadStage, f32)>>,
+}
+
+impl LoadProgress {
+    /// Progress of a blocking load nobody is watching.
+    pub fn none() -> Self {
+        Self { updater: None }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.updater
+            .as_ref()
+            .is_some_and(|updater| updater.has_no_receiver())
+    }
+
+    pub fn report(&self, stage: LoadStage, fraction: f32) -> Result<(), LoadError> {
+        match &self.updater {
+            Some(updater) => updater
+                .update((stage, fraction.clamp(0f32, 1f32)))
+                .map_err(|_| LoadError::Cancelled),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reports how far into the file the VDB reader got.
+pub struct ProgressReader<'a, R> {
+    inner: R,
+    position: u64,
+    length: u64,
+    progress: &'a LoadProgress,
+}
+
+impl<'a, R: Seek> ProgressReader<'a, R> {
+    pub fn new(mut inner: R, progress: &'a LoadProgress) -> io::Result<Self> {
+        let length = inner.seek(SeekFrom::End(0))?;
+        inner.seek(SeekFrom::Start(0))?;
+        Ok(Self {
+            inner,
+            position: 0,
+            length: length.max(1),
+            progress,
+        })
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        let fraction = self.position as f32 / self.length as f32;
+        // not `Interrupted`, readers retry on that
+        self.progress
+            .report(LoadStage::Reading, fraction)
+            .map_err(|_| io::Error::other("loading cancelled"))?;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for ProgressReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+/// A VDB file being loaded on a worker thread. Dropping the task cancels the load.
+pub struct LoadTask {
+    pub name: String,
+    progress: Receiver<(LoadStage, f32)>,
+    result: mpsc::Receiver<Result<VolumeData, LoadError>>,
+    /// Volume generation that has to reach the GPU once the data is in the scene.
+    upload_generation: Option<u64>,
+}
+
+impl LoadTask {
+    pub fn spawn(path: &str, options: LoadOptions) -> Self {
+        let (progress, updater) =
+            single_value_channel::channel_starting_with((LoadStage::Reading, 0f32));
+        let (sender, result) = mpsc::channel();
+
+        let worker_path = path.to_string();
+        thread::spawn(move || {
+            let progress = LoadProgress {
+                updater: Some(updater),
+            };
+            let result = VolumeData::load_with_progress(&worker_path, &options, &progress);
+            let _ = sender.send(result);
+        });
+
+        let name = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or(path.to_string());
+
+        Self {
+            name,
+            progress,
+            result,
+            upload_generation: None,
+        }
+    }
+
+    pub fn progress(&mut self) -> (LoadStage, f32) {
+        match self.upload_generation {
+            Some(_) => (LoadStage::Uploading, 1f32),
+            None => *self.progress.latest(),
+        }
+    }
+
+    /// Returns the loaded volume once the worker is done.
+    pub fn try_take(&mut self) -> Option<Result<VolumeData, String>> {
+        if self.upload_generation.is_some() {
+            return None;
+        }
+        match self.result.try_recv() {
+            Ok(result) => Some(result.map_err(|err| err.to_string())),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err("loader thread stopped".to_string())),
+        }
+    }
+
+    pub fn wait_for_upload(&mut self, generation: u64) {
+        self.upload_generation = Some(generation);
+    }
+
+    pub fn is_uploaded(&self, uploaded_generation: u64) -> bool {
+        self.upload_generation
+            .is_some_and(|generation| uploaded_generation >= generation)
+    }
+}
+
+/// Volumes packed again with new options on a worker thread, each one paired with the
+/// data it was packed from.
+type Repacked = Vec<(Arc<VolumeData>, VolumeData)>;
+
+/// Loaded volumes being packed with new options on a worker thread. Dropping the task
+/// cancels the repack.
+pub struct RepackTask {
+    progress: Receiver<(LoadStage, f32)>,
+    result: mpsc::Receiver<Result<Repacked, LoadError>>,
+}
+
+impl RepackTask {
+    pub fn spawn(volumes: Vec<Arc<VolumeData>>, options: LoadOptions) -> Self {
+        let (progress, updater) =
+            single_value_channel::channel_starting_with((LoadStage::Packing, 0f32));
+        let (sender, result) = mpsc::channel();
+
+        thread::spawn(move || {
+            let progress = LoadProgress {
+                updater: Some(updater),
+            };
+            let result = volumes
+                .into_iter()
+                .map(|data| {
+                    let repacked = data.repacked(&options, &progress)?;
+                    Ok((data, repacked))
+                })
+                .collect();
+            let _ = sender.send(result);
+        });
+
+        Self { progress, result }
+    }
+
+    /// Packed fraction of the volume being repacked.
+    pub fn progress(&mut self) -> f32 {
+        self.progress.latest().1
+    }
+
+    /// Returns the repacked volumes once the worker is done.
+    pub fn try_take(&mut self) -> Option<Result<Repacked, String>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result.map_err(|err| err.to_string())),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err("repack thread stopped".to_string())),
+        }
+    }
+}
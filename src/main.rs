@@ -1,7 +1,17 @@
 mod aabb;
+mod bench;
+mod cli;
 mod editor;
+mod headless;
+mod image_file;
+mod loader;
+mod profiler;
+mod renderer;
+mod sequence;
+mod tone_mapping;
 mod volume_grid;
 
+use cli::Args;
 use editor::Editor;
 use eframe::wgpu;
 
@@ -12,30 +22,62 @@ const CAMERA_MOVE_SPEED: f32 = 2f32;
 const CAMERA_ROTATION_SPEED: f32 = 2f32;
 const WORKGROUP_SIZE: [u32; 2] = [16u32, 16u32];
 
+fn device_descriptor(adapter: &wgpu::Adapter) -> wgpu::DeviceDescriptor<'static> {
+    let base_limits = if adapter.get_info().backend == wgpu::Backend::Gl {
+        // webgl2 limits have no compute shaders
+        wgpu::Limits::downlevel_defaults()
+    } else {
+        wgpu::Limits::default()
+    };
+
+    wgpu::DeviceDescriptor {
+        label: Some("egui wgpu device"),
+        // software adapters used by the headless renderer may lack it
+        required_features: (wgpu::Features::FLOAT32_FILTERABLE
+            | wgpu::Features::TIMESTAMP_QUERY
+            | wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES)
+            & adapter.features(),
+        memory_hints: wgpu::MemoryHints::Performance,
+        required_limits: wgpu::Limits {
+            max_storage_buffer_binding_size: 200000000
+                .min(adapter.limits().max_storage_buffer_binding_size),
+            // When using a depth buffer, we have to be able to create a texture
+            // large enough for the entire surface, and we want to support 4k+ displays.
+            max_texture_dimension_2d: 8192,
+            ..base_limits
+        },
+    }
+}
+
 fn main() -> Result<(), eframe::Error> {
     tracing_subscriber::fmt::init();
 
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(2);
+        }
+    };
+
+    if args.bench.is_some() {
+        if let Err(err) = bench::run(&args) {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if args.is_headless() {
+        if let Err(err) = headless::run(&args) {
+            log::error!("{}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let options = egui_wgpu::WgpuConfiguration {
-        device_descriptor: Arc::new(|adapter| {
-            let base_limits = if adapter.get_info().backend == wgpu::Backend::Gl {
-                wgpu::Limits::downlevel_webgl2_defaults()
-            } else {
-                wgpu::Limits::default()
-            };
-
-            wgpu::DeviceDescriptor {
-                label: Some("egui wgpu device"),
-                required_features: wgpu::Features::FLOAT32_FILTERABLE,
-                memory_hints: wgpu::MemoryHints::Performance,
-                required_limits: wgpu::Limits {
-                    max_storage_buffer_binding_size: 200000000,
-                    // When using a depth buffer, we have to be able to create a texture
-                    // large enough for the entire surface, and we want to support 4k+ displays.
-                    max_texture_dimension_2d: 8192,
-                    ..base_limits
-                },
-            }
-        }),
+        device_descriptor: Arc::new(device_descriptor),
         ..Default::default()
     };
     let options = eframe::NativeOptions {
@@ -49,6 +91,13 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "Strelka",
         options,
-        Box::new(|cc| Ok(Box::new(Editor::new(cc, SCREEN_SIZE[0], SCREEN_SIZE[1])))),
+        Box::new(move |cc| {
+            Ok(Box::new(Editor::new(
+                cc,
+                SCREEN_SIZE[0],
+                SCREEN_SIZE[1],
+                &args.inputs,
+            )))
+        }),
     )
 }
@@ -0,0 +1,286 @@
+use bytemuck::{Pod, Zeroable};
+use eframe::wgpu::{self, BufferUsages};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Passes timed with a pair of timestamps, in query order.
+#[derive(Clone, Copy)]
+pub enum TimedPass {
+    Compute,
+    Blit,
+}
+const TIMED_PASS_COUNT: usize = 2;
+const QUERY_COUNT: u32 = 2 * TIMED_PASS_COUNT as u32;
+const TIMESTAMPS_SIZE: u64 = QUERY_COUNT as u64 * wgpu::QUERY_SIZE as u64;
+
+/// Totals of one dispatch, must match RayStats in compute.wgsl.
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy, Default)]
+pub struct RayStats {
+    /// Rays that took at least one sample.
+    pub rays: u32,
+    /// Low and high words of the sample count.
+    samples: [u32; 2],
+    light_samples: [u32; 2],
+    /// Rays stopped by opacity or a hit before leaving the volumes.
+    pub early_terminated: u32,
+}
+
+fn total(words: [u32; 2]) -> u64 {
+    (words[1] as u64) << 32 | words[0] as u64
+}
+
+impl RayStats {
+    pub fn samples_per_ray(&self) -> f32 {
+        (total(self.samples) as f64 / self.rays.max(1) as f64) as f32
+    }
+
+    pub fn light_samples_per_ray(&self) -> f32 {
+        (total(self.light_samples) as f64 / self.rays.max(1) as f64) as f32
+    }
+
+    pub fn early_terminated_percent(&self) -> f32 {
+        100f32 * self.early_terminated as f32 / self.rays.max(1) as f32
+    }
+}
+
+/// GPU measurements of one render.
+#[derive(Clone, Copy)]
+pub struct GpuFrame {
+    /// Milliseconds per `TimedPass`, `None` if the adapter can't time it.
+    pub pass_ms: [Option<f32>; TIMED_PASS_COUNT],
+    pub stats: RayStats,
+}
+
+impl GpuFrame {
+    pub fn pass_ms(&self, pass: TimedPass) -> Option<f32> {
+        self.pass_ms[pass as usize]
+    }
+}
+
+enum ReadbackState {
+    Idle,
+    /// The copy into the readback buffer is recorded, it is mapped after it was submitted.
+    Copied,
+    Mapping,
+}
+
+/// Times the compute and blit passes with timestamp queries and reads back the ray
+/// statistics. Without `TIMESTAMP_QUERY` only the statistics are collected.
+pub struct GpuProfiler {
+    query_set: Option<wgpu::QuerySet>,
+    /// Timestamps can be written inside a pass begun by someone else, like egui's.
+    inside_passes: bool,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    resolve_buffer: wgpu::Buffer,
+    /// Timestamps followed by the ray stats.
+    readback_buffer: wgpu::Buffer,
+    /// Passes that wrote their timestamps since the last copy.
+    written: [AtomicBool; TIMED_PASS_COUNT],
+    /// Passes timed by the copy in the readback buffer.
+    copied: [bool; TIMED_PASS_COUNT],
+    rendered: bool,
+    state: ReadbackState,
+    mapped: Arc<AtomicBool>,
+}
+
+impl GpuProfiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let features = device.features();
+        let query_set = features.contains(wgpu::Features::TIMESTAMP_QUERY).then(|| {
+            device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Pass timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: QUERY_COUNT,
+            })
+        });
+        if query_set.is_none() {
+            log::info!("Timestamp queries are not supported, GPU pass times are not measured");
+        }
+
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Timestamp resolve buffer"),
+            size: TIMESTAMPS_SIZE,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Profiler readback buffer"),
+            size: TIMESTAMPS_SIZE + std::mem::size_of::<RayStats>() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            query_set,
+            inside_passes: features.contains(wgpu::Features::TIMESTAMP_QUERY_INSIDE_PASSES),
+            period: queue.get_timestamp_period(),
+            resolve_buffer,
+            readback_buffer,
+            written: [AtomicBool::new(false), AtomicBool::new(false)],
+            copied: [false; TIMED_PASS_COUNT],
+            rendered: false,
+            state: ReadbackState::Idle,
+            mapped: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn has_timestamps(&self) -> bool {
+        self.query_set.is_some()
+    }
+
+    /// Whether passes begun by someone else can be timed with `write_timestamp`.
+    pub fn has_timestamps_inside_passes(&self) -> bool {
+        self.query_set.is_some() && self.inside_passes
+    }
+
+    /// Measurements of a render are still being read back.
+    pub fn is_busy(&self) -> bool {
+        self.rendered || !matches!(self.state, ReadbackState::Idle)
+    }
+
+    /// Timestamps for the compute pass, call it once per dispatch.
+    pub fn compute_timestamp_writes(&mut self) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        self.rendered = true;
+        let query_set = self.query_set.as_ref()?;
+        self.written[TimedPass::Compute as usize].store(true, Ordering::Relaxed);
+        let index = 2 * TimedPass::Compute as u32;
+        Some(wgpu::ComputePassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(index),
+            end_of_pass_write_index: Some(index + 1),
+        })
+    }
+
+    /// Timestamps for a render pass we begin ourselves.
+    pub fn render_timestamp_writes(
+        &self,
+        pass: TimedPass,
+    ) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        let query_set = self.query_set.as_ref()?;
+        self.written[pass as usize].store(true, Ordering::Relaxed);
+        let index = 2 * pass as u32;
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(index),
+            end_of_pass_write_index: Some(index + 1),
+        })
+    }
+
+    /// Writes the begin or end timestamp of a pass from inside a render pass.
+    pub fn write_timestamp(
+        &self,
+        render_pass: &mut wgpu::RenderPass<'_>,
+        pass: TimedPass,
+        end: bool,
+    ) {
+        let Some(query_set) = self.query_set.as_ref().filter(|_| self.inside_passes) else {
+            return;
+        };
+        render_pass.write_timestamp(query_set, 2 * pass as u32 + end as u32);
+        if end {
+            self.written[pass as usize].store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Records copying the measurements of the last render into the readback buffer.
+    fn copy(&mut self, encoder: &mut wgpu::CommandEncoder, ray_stats_buffer: &wgpu::Buffer) {
+        for (copied, written) in self.copied.iter_mut().zip(&self.written) {
+            *copied = written.swap(false, Ordering::Relaxed);
+        }
+        if let Some(query_set) = &self.query_set {
+            encoder.resolve_query_set(query_set, 0..QUERY_COUNT, &self.resolve_buffer, 0);
+            encoder.copy_buffer_to_buffer(
+                &self.resolve_buffer,
+                0,
+                &self.readback_buffer,
+                0,
+                TIMESTAMPS_SIZE,
+            );
+        }
+        encoder.copy_buffer_to_buffer(
+            ray_stats_buffer,
+            0,
+            &self.readback_buffer,
+            TIMESTAMPS_SIZE,
+            std::mem::size_of::<RayStats>() as u64,
+        );
+        self.rendered = false;
+    }
+
+    fn map(&mut self) {
+        self.mapped.store(false, Ordering::SeqCst);
+        let mapped = self.mapped.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if let Err(err) = result {
+                    log::error!("Failed to map profiler buffer: {}", err);
+                }
+                mapped.store(true, Ordering::SeqCst);
+            });
+    }
+
+    fn read(&self) -> GpuFrame {
+        let mapped = self.readback_buffer.slice(..).get_mapped_range();
+        let timestamps: &[u64] = bytemuck::cast_slice(&mapped[..TIMESTAMPS_SIZE as usize]);
+        let stats: RayStats = bytemuck::pod_read_unaligned(&mapped[TIMESTAMPS_SIZE as usize..]);
+        let mut pass_ms = [None; TIMED_PASS_COUNT];
+        for (i, ms) in pass_ms.iter_mut().enumerate() {
+            let (begin, end) = (timestamps[2 * i], timestamps[2 * i + 1]);
+            if self.copied[i] && self.query_set.is_some() && end > begin {
+                *ms = Some((end - begin) as f32 * self.period / 1e6f32);
+            }
+        }
+        drop(mapped);
+        self.readback_buffer.unmap();
+        GpuFrame { pass_ms, stats }
+    }
+
+    /// Moves the readback along by one step per frame without ever waiting for the GPU,
+    /// so measurements arrive a few frames late. Call it before recording a new render
+    /// into the encoder. Returns the measurements of a render once they are read.
+    pub fn collect(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        ray_stats_buffer: &wgpu::Buffer,
+    ) -> Option<GpuFrame> {
+        device.poll(wgpu::Maintain::Poll);
+        let mut frame = None;
+        match self.state {
+            ReadbackState::Copied => {
+                self.map();
+                self.state = ReadbackState::Mapping;
+            }
+            ReadbackState::Mapping if self.mapped.load(Ordering::SeqCst) => {
+                frame = Some(self.read());
+                self.state = ReadbackState::Idle;
+            }
+            _ => {}
+        }
+        if matches!(self.state, ReadbackState::Idle) && self.rendered {
+            self.copy(encoder, ray_stats_buffer);
+            self.state = ReadbackState::Copied;
+        }
+        frame
+    }
+
+    /// Reads the measurements of the render just submitted, blocking until the GPU is done.
+    pub fn read_blocking(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        ray_stats_buffer: &wgpu::Buffer,
+    ) -> GpuFrame {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Profiler encoder"),
+        });
+        self.copy(&mut encoder, ray_stats_buffer);
+        queue.submit(Some(encoder.finish()));
+        self.map();
+        device.poll(wgpu::Maintain::Wait);
+        self.read()
+    }
+}
@@ -0,0 +1,666 @@
+mod light_cache;
+mod weights;
+
+use crate::editor::settings::{RenderMode, Settings, MAX_CLIP_PLANES, MAX_VOLUMES};
+use crate::image_file::{split_rgba16f, ExrChannel};
+use crate::profiler::RayStats;
+use crate::volume_grid::{GridClass, VolumeGridStatic};
+use crate::WORKGROUP_SIZE;
+use bytemuck::{Pod, Zeroable};
+use eframe::wgpu::{self, include_wgsl, BufferUsages};
+use light_cache::{cache_size, LightCache};
+use std::time::{Duration, Instant};
+pub use weights::max_weights_bytes;
+use weights::WeightsBuffer;
+
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy)]
+pub struct Uniforms {
+    color: [f32; 4],
+    camera_to_world: [[f32; 4]; 4],
+    light_dir: [f32; 4],
+    light_col: [f32; 4],
+    step_size: f32,
+    voxel_size: f32,
+    volume_count: u32,
+    render_mode: u32,
+    iso_value: f32,
+    cap_clip_planes: u32,
+    density_scale: f32,
+    density_gamma: f32,
+    clip_planes: [[f32; 4]; MAX_CLIP_PLANES],
+    density_contrast: f32,
+    density_invert: u32,
+    transparent_background: u32,
+    ray_stats: u32,
+    pixel_offset: [f32; 2],
+    adaptive_step: u32,
+    max_step_scale: f32,
+    step_footprint: f32,
+    light_step: f32,
+    max_light_steps: u32,
+    jitter: u32,
+    light_cache_min: [f32; 4],
+    light_cache_max: [f32; 4],
+    light_cache_size: [u32; 3],
+    light_cache: u32,
+    //samples_per_pixel: u32,
+}
+
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy)]
+pub struct VolumeInstanceUniform {
+    grid: VolumeGridStatic,
+    /// absorption, scattering, g
+    material: [f32; 4],
+    /// Index of the first block of this volume in the weights buffer.
+    weights_offset: u32,
+    quantization: u32,
+    /// Scales the stored densities, the interior density of level sets.
+    density_scale: f32,
+    _padding: u32,
+    /// rgb is the emitted radiance per unit density.
+    emission: [f32; 4],
+}
+
+/// Radiance is kept unclamped and tone mapped when displayed.
+pub const RESULT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Textures with the arbitrary output variables, in binding order after the result:
+/// direct lighting + transmittance, emission + density integral, depths.
+pub const AOV_TEXTURE_COUNT: usize = 3;
+const AOV_LABELS: [&str; AOV_TEXTURE_COUNT] = ["Lighting AOV", "Emission AOV", "Depth AOV"];
+/// EXR channel names of the components of the result and AOV textures,
+/// must match the AOV texture layout in compute.wgsl.
+pub const CHANNEL_NAMES: [[Option<&str>; 4]; AOV_TEXTURE_COUNT + 1] = [
+    [Some("R"), Some("G"), Some("B"), Some("A")],
+    [
+        Some("direct.R"),
+        Some("direct.G"),
+        Some("direct.B"),
+        Some("transmittance.Y"),
+    ],
+    [
+        Some("emission.R"),
+        Some("emission.G"),
+        Some("emission.B"),
+        Some("density.Y"),
+    ],
+    [Some("depth.Z"), Some("weightedDepth.Z"), None, None],
+];
+
+/// Compute part of the renderer, shared by the editor viewport and the headless renderer.
+pub struct VolumeRenderer {
+    pub width: u32,
+    pub height: u32,
+    pub result_texture: wgpu::Texture,
+    pub result_texture_view: wgpu::TextureView,
+    pub aov_textures: [wgpu::Texture; AOV_TEXTURE_COUNT],
+    pub aov_texture_views: [wgpu::TextureView; AOV_TEXTURE_COUNT],
+    compute_pipeline: wgpu::ComputePipeline,
+    compute_bind_group: wgpu::BindGroup,
+    compute_bind_group_layout: wgpu::BindGroupLayout,
+    light_cache: LightCache,
+    uniforms_buffer: wgpu::Buffer,
+    volumes_buffer: wgpu::Buffer,
+    weights: WeightsBuffer,
+    /// Totals of the last dispatch, see `RayStats`.
+    pub ray_stats_buffer: wgpu::Buffer,
+    /// Counts the rays into `ray_stats_buffer`, renders that don't show the stats skip it.
+    pub ray_stats: bool,
+    uploaded_generation: Option<u64>,
+    /// Subpixel position of the rays in pixels.
+    pixel_offset: [f32; 2],
+    /// Instance and uniform bytes of the last upload, to skip renders that change nothing.
+    uploaded_uniforms: Vec<u8>,
+    /// Time the last `prepare` spent uploading the volumes, if it had to.
+    pub upload_time: Option<Duration>,
+}
+
+/// Bindings of the buffers of the compute pass: volumes, weights, uniforms and ray statistics.
+const COMPUTE_BUFFER_BINDINGS: [u32; 4] = [1, 2, 3, 7];
+
+fn create_compute_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    result_texture_view: &wgpu::TextureView,
+    aov_texture_views: &[wgpu::TextureView; AOV_TEXTURE_COUNT],
+    buffers: [&wgpu::Buffer; 4],
+) -> wgpu::BindGroup {
+    let texture_views = std::iter::once(result_texture_view).chain(aov_texture_views);
+    let mut entries: Vec<wgpu::BindGroupEntry> = [0, 4, 5, 6]
+        .into_iter()
+        .zip(texture_views)
+        .map(|(binding, view)| wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::TextureView(view),
+        })
+        .collect();
+    entries.extend(
+        COMPUTE_BUFFER_BINDINGS
+            .into_iter()
+            .zip(buffers)
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding,
+                resource: buffer.as_entire_binding(),
+            }),
+    );
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Bind group compute"),
+        layout,
+        entries: &entries,
+    })
+}
+
+fn storage_texture_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: RESULT_FORMAT,
+            view_dimension: wgpu::TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+fn create_result_texture(
+    device: &wgpu::Device,
+    label: &str,
+    size: wgpu::Extent3d,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: RESULT_FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+fn storage_buffer_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+impl VolumeRenderer {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        Self::with_weights(device, width, height, WeightsBuffer::new(device))
+    }
+
+    /// A renderer of another size that binds the weights `other` already uploaded
+    /// instead of a copy of them.
+    pub fn sharing_weights(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        other: &VolumeRenderer,
+    ) -> Self {
+        let mut renderer = Self::with_weights(device, width, height, other.weights.clone());
+        renderer.uploaded_generation = other.uploaded_generation;
+        renderer
+    }
+
+    fn with_weights(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        weights: WeightsBuffer,
+    ) -> Self {
+        let cs_module = device.create_shader_module(include_wgsl!("shaders/compute.wgsl"));
+
+        let texture_size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let (result_texture, result_texture_view) =
+            create_result_texture(device, "Result texture", texture_size);
+        let (aov_textures, aov_texture_views): (Vec<_>, Vec<_>) = AOV_LABELS
+            .iter()
+            .map(|label| create_result_texture(device, label, texture_size))
+            .unzip();
+        let aov_textures: [wgpu::Texture; AOV_TEXTURE_COUNT] = aov_textures.try_into().unwrap();
+        let aov_texture_views: [wgpu::TextureView; AOV_TEXTURE_COUNT] =
+            aov_texture_views.try_into().unwrap();
+
+        let uniforms_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Uniforms buffer"),
+            size: std::mem::size_of::<Uniforms>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let volumes_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Volumes buffer"),
+            size: (std::mem::size_of::<VolumeInstanceUniform>() * MAX_VOLUMES) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let ray_stats_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Ray stats buffer"),
+            size: std::mem::size_of::<RayStats>() as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let compute_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Compute bind group layout"),
+                entries: &[
+                    storage_texture_layout_entry(0),
+                    storage_buffer_layout_entry(1),
+                    storage_buffer_layout_entry(2),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    storage_texture_layout_entry(4),
+                    storage_texture_layout_entry(5),
+                    storage_texture_layout_entry(6),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+
+        let compute_bind_group = create_compute_bind_group(
+            device,
+            &compute_bind_group_layout,
+            &result_texture_view,
+            &aov_texture_views,
+            [
+                &volumes_buffer,
+                weights.buffer(),
+                &uniforms_buffer,
+                &ray_stats_buffer,
+            ],
+        );
+
+        let light_cache = LightCache::new(device, &cs_module, &compute_bind_group_layout);
+
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Compute pipeline layout"),
+                bind_group_layouts: &[
+                    &compute_bind_group_layout,
+                    light_cache.sample_bind_group_layout(),
+                ],
+                push_constant_ranges: &[],
+            });
+
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute pipeline"),
+            layout: Some(&compute_pipeline_layout),
+            module: &cs_module,
+            entry_point: "main",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        Self {
+            width,
+            height,
+            result_texture,
+            result_texture_view,
+            aov_textures,
+            aov_texture_views,
+            compute_pipeline,
+            compute_bind_group,
+            compute_bind_group_layout,
+            light_cache,
+            uniforms_buffer,
+            volumes_buffer,
+            weights,
+            ray_stats_buffer,
+            ray_stats: true,
+            uploaded_generation: None,
+            pixel_offset: [0f32; 2],
+            uploaded_uniforms: Vec::new(),
+            upload_time: None,
+        }
+    }
+
+    /// Writes the weights of the volumes that changed, binds the buffer again if it had to
+    /// be replaced. Volumes that don't fit are logged and left out of the render.
+    fn upload_weights(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, settings: &Settings) {
+        self.uploaded_generation = Some(settings.volumes_generation);
+        match self.weights.upload(device, queue, settings) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(err) => {
+                log::error!("{}", err);
+                return;
+            }
+        }
+        self.compute_bind_group = create_compute_bind_group(
+            device,
+            &self.compute_bind_group_layout,
+            &self.result_texture_view,
+            &self.aov_texture_views,
+            [
+                &self.volumes_buffer,
+                self.weights.buffer(),
+                &self.uniforms_buffer,
+                &self.ray_stats_buffer,
+            ],
+        );
+    }
+
+    /// Uploads the settings, returns false if the last render already shows them.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        settings: &Settings,
+    ) -> bool {
+        let volumes_changed = self.uploaded_generation != Some(settings.volumes_generation);
+        self.upload_time = None;
+        if volumes_changed {
+            let start = Instant::now();
+            self.upload_weights(device, queue, settings);
+            self.upload_time = Some(start.elapsed());
+        }
+
+        let mut instances = [VolumeInstanceUniform::zeroed(); MAX_VOLUMES];
+        let mut volume_count = 0usize;
+        let mut voxel_size = f32::MAX;
+        let visible_volumes = settings
+            .volumes
+            .iter()
+            .zip(self.weights.offsets())
+            .filter(|(volume, _)| volume.visible)
+            .take(MAX_VOLUMES);
+        for (volume, weights_offset) in visible_volumes {
+            let grid = volume.data.grid.instanced(&volume.object_to_world());
+            voxel_size = voxel_size.min(grid.min_voxel_size());
+            let material = volume.material;
+            instances[volume_count] = VolumeInstanceUniform {
+                grid,
+                material: [material.absorption, material.scattering, material.g, 0f32],
+                weights_offset,
+                quantization: volume.data.options.quantization.as_u32(),
+                density_scale: match volume.data.dense.class {
+                    GridClass::LevelSet => settings.load_options.level_set.interior_density,
+                    _ => 1f32,
+                },
+                _padding: 0u32,
+                emission: (material.emission_color * material.emission)
+                    .extend(0f32)
+                    .to_array(),
+            };
+            volume_count += 1;
+        }
+        if volume_count == 0 {
+            voxel_size = 1f32;
+        }
+
+        let color = settings.background_color;
+        let camera_to_world = settings.matrix;
+        let light_color = settings.light_color * settings.lightness;
+
+        // disabled planes are uploaded with a zero normal and skipped by the shader
+        let scene_bbox = settings.scene_bbox();
+        let mut clip_planes = [[0f32; 4]; MAX_CLIP_PLANES];
+        for (dst, plane) in clip_planes.iter_mut().zip(settings.clip_planes.iter()) {
+            if plane.enabled {
+                let normal = plane.unit_normal();
+                let distance = normal.dot(plane.origin(&scene_bbox));
+                *dst = [normal.x, normal.y, normal.z, distance];
+            }
+        }
+
+        let light_cache = settings.light_cache
+            && settings.render_mode == RenderMode::PhysicallyBased
+            && volume_count > 0;
+        let light_cache_size = cache_size(&scene_bbox, voxel_size);
+
+        let adaptive = &settings.adaptive_step;
+        let uniforms = Uniforms {
+            color: [color[0], color[1], color[2], 1f32],
+            camera_to_world: camera_to_world.to_cols_array_2d(),
+            light_col: [light_color.x, light_color.y, light_color.z, 1.0],
+            light_dir: [1.0, 1.0, 1.0, 1.0],
+            step_size: settings.ray_marching_step,
+            voxel_size,
+            volume_count: volume_count as u32,
+            render_mode: settings.render_mode.as_u32(),
+            iso_value: settings.iso_value,
+            cap_clip_planes: settings.cap_clip_planes as u32,
+            density_scale: settings.density_scale,
+            density_gamma: settings.density_gamma,
+            clip_planes,
+            density_contrast: settings.density_contrast,
+            density_invert: settings.density_invert as u32,
+            transparent_background: settings.transparent_background as u32,
+            ray_stats: self.ray_stats as u32,
+            pixel_offset: self.pixel_offset,
+            adaptive_step: adaptive.enabled as u32,
+            max_step_scale: adaptive.max_scale,
+            step_footprint: adaptive.footprint,
+            light_step: adaptive.light_step,
+            max_light_steps: adaptive.max_light_steps,
+            jitter: adaptive.jitter as u32,
+            light_cache_min: scene_bbox.min().extend(0f32).to_array(),
+            light_cache_max: scene_bbox.max().extend(0f32).to_array(),
+            light_cache_size,
+            light_cache: light_cache as u32,
+            //samples_per_pixel: settings.spp,
+        };
+
+        let instances_bytes: &[u8] = bytemuck::cast_slice(&instances);
+        let uniforms_bytes = bytemuck::bytes_of(&uniforms);
+
+        // the transmittance only depends on the volumes and the light, not the camera
+        let mut light_cache_inputs = Vec::new();
+        if light_cache {
+            light_cache_inputs = [
+                instances_bytes,
+                bytemuck::bytes_of(&settings.volumes_generation),
+                bytemuck::bytes_of(&uniforms.light_dir),
+                bytemuck::bytes_of(&uniforms.step_size),
+                bytemuck::bytes_of(&uniforms.voxel_size),
+                bytemuck::bytes_of(&uniforms.volume_count),
+                bytemuck::bytes_of(&uniforms.density_scale),
+                bytemuck::bytes_of(&uniforms.density_gamma),
+                bytemuck::bytes_of(&uniforms.density_contrast),
+                bytemuck::bytes_of(&uniforms.density_invert),
+                bytemuck::bytes_of(&uniforms.clip_planes),
+                bytemuck::bytes_of(&uniforms.adaptive_step),
+                bytemuck::bytes_of(&uniforms.light_step),
+                bytemuck::bytes_of(&uniforms.max_light_steps),
+                bytemuck::bytes_of(&uniforms.light_cache_min),
+                bytemuck::bytes_of(&uniforms.light_cache_max),
+            ]
+            .concat();
+        }
+        self.light_cache
+            .update(device, light_cache_size, light_cache_inputs);
+        let bytes = [instances_bytes, uniforms_bytes].concat();
+        if !volumes_changed && bytes == self.uploaded_uniforms {
+            return false;
+        }
+
+        queue.write_buffer(&self.volumes_buffer, 0, instances_bytes);
+        queue.write_buffer(&self.uniforms_buffer, 0, uniforms_bytes);
+        self.uploaded_uniforms = bytes;
+        true
+    }
+
+    /// Moves the rays within their pixels without touching the rest of the uniforms,
+    /// so that samples of one image can be accumulated.
+    pub fn set_pixel_offset(&mut self, queue: &wgpu::Queue, offset: [f32; 2]) {
+        self.pixel_offset = offset;
+        queue.write_buffer(
+            &self.uniforms_buffer,
+            std::mem::offset_of!(Uniforms, pixel_offset) as u64,
+            bytemuck::bytes_of(&offset),
+        );
+    }
+
+    /// The result and AOV textures with the EXR channel names of their components.
+    pub fn channel_textures(&self) -> [(&wgpu::Texture, [Option<&'static str>; 4]); 4] {
+        let [lighting, emission, depth] = &self.aov_textures;
+        let textures = [&self.result_texture, lighting, emission, depth];
+        std::array::from_fn(|i| (textures[i], CHANNEL_NAMES[i]))
+    }
+
+    /// Reads back the beauty render and every AOV as EXR channels.
+    pub fn read_channels(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<ExrChannel> {
+        self.channel_textures()
+            .into_iter()
+            .flat_map(|(texture, names)| {
+                split_rgba16f(&read_texture(device, queue, texture), names)
+            })
+            .collect()
+    }
+
+    /// Renders the volumes, `timestamp_writes` come from a `GpuProfiler`.
+    /// Rebuilds the light cache first if its inputs changed since the last dispatch.
+    pub fn dispatch(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        timestamp_writes: Option<wgpu::ComputePassTimestampWrites>,
+    ) {
+        self.light_cache.build(encoder, &self.compute_bind_group);
+        encoder.clear_buffer(&self.ray_stats_buffer, 0, None);
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compute"),
+            timestamp_writes,
+        });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
+        compute_pass.set_bind_group(1, self.light_cache.sample_bind_group(), &[]);
+        // stores past the edge of the texture are dropped
+        compute_pass.dispatch_workgroups(
+            self.width.div_ceil(WORKGROUP_SIZE[0]),
+            self.height.div_ceil(WORKGROUP_SIZE[1]),
+            1,
+        );
+    }
+}
+
+/// A texture copied into a buffer that the CPU can map once the copy is done.
+pub struct Readback {
+    buffer: wgpu::Buffer,
+    unpadded_bytes_per_row: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl Readback {
+    pub fn new(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) -> Self {
+        let height = texture.height();
+        let bytes_per_pixel = texture.format().block_copy_size(None).unwrap_or(4u32);
+        let unpadded_bytes_per_row = texture.width() * bytes_per_pixel;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback staging buffer"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+
+        Self {
+            buffer,
+            unpadded_bytes_per_row,
+            padded_bytes_per_row,
+        }
+    }
+
+    /// Requests the mapping after the copy is submitted, the callback runs from `Device::poll`.
+    pub fn map(
+        &self,
+        callback: impl FnOnce(Result<(), wgpu::BufferAsyncError>) + wgpu::WasmNotSend + 'static,
+    ) {
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, callback);
+    }
+
+    /// Tightly packed rows of a mapped buffer.
+    pub fn read(&self) -> Vec<u8> {
+        let mapped = self.buffer.slice(..).get_mapped_range();
+        let mut pixels = Vec::with_capacity(mapped.len());
+        for row in mapped.chunks(self.padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..self.unpadded_bytes_per_row as usize]);
+        }
+        drop(mapped);
+        self.buffer.unmap();
+        pixels
+    }
+}
+
+/// Copies a texture back to the CPU as tightly packed rows, blocking until the GPU is done.
+pub fn read_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Vec<u8> {
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback encoder"),
+    });
+    let readback = Readback::new(device, &mut encoder, texture);
+    queue.submit(Some(encoder.finish()));
+
+    readback.map(|result| {
+        if let Err(err) = result {
+            log::error!("Failed to map readback buffer: {}", err);
+        }
+    });
+    device.poll(wgpu::Maintain::Wait);
+    readback.read()
+}
@@ -0,0 +1,273 @@
+use crate::aabb::Aabb;
+use eframe::wgpu;
+
+/// Texels along the longest axis of the cache at most.
+const MAX_LIGHT_CACHE_SIZE: u32 = 128;
+/// Voxels of the finest volume covered by one texel.
+const VOXELS_PER_TEXEL: f32 = 2f32;
+// must match the workgroup size of build_light_cache in compute.wgsl
+const WORKGROUP_SIZE: u32 = 4;
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const BYTES_PER_TEXEL: u32 = 8;
+
+/// Transmittance towards the light over the scene bbox, so that the main march reads
+/// it with one texture fetch instead of marching a ray towards the light per sample.
+pub struct LightCache {
+    build_pipeline: wgpu::ComputePipeline,
+    build_bind_group_layout: wgpu::BindGroupLayout,
+    build_bind_group: wgpu::BindGroup,
+    sample_bind_group_layout: wgpu::BindGroupLayout,
+    sample_bind_group: wgpu::BindGroup,
+    sampler: wgpu::Sampler,
+    texture: wgpu::Texture,
+    /// Written by the build and copied to `texture`, storage textures of three
+    /// dimensions can't be written on every backend.
+    buffer: wgpu::Buffer,
+    /// Everything the transmittance depends on as of the last build, empty if it
+    /// has to be built.
+    built_inputs: Vec<u8>,
+    inputs: Vec<u8>,
+}
+
+/// Bytes per row of the build buffer, must match LIGHT_CACHE_ROW_ALIGNMENT in compute.wgsl.
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    (width * BYTES_PER_TEXEL).div_ceil(align) * align
+}
+
+fn create_texture(
+    device: &wgpu::Device,
+    size: [u32; 3],
+) -> (wgpu::Texture, wgpu::TextureView, wgpu::Buffer) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Light cache texture"),
+        size: wgpu::Extent3d {
+            width: size[0],
+            height: size[1],
+            depth_or_array_layers: size[2],
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Light cache buffer"),
+        size: (padded_bytes_per_row(size[0]) * size[1] * size[2]) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    (texture, view, buffer)
+}
+
+/// Resolution covering the bbox with texels of about two voxels.
+pub fn cache_size(bbox: &Aabb, voxel_size: f32) -> [u32; 3] {
+    let texels = 2f32 * bbox.half_extent() / (voxel_size * VOXELS_PER_TEXEL);
+    texels
+        .to_array()
+        .map(|texels| (texels.ceil() as u32).clamp(1, MAX_LIGHT_CACHE_SIZE))
+}
+
+impl LightCache {
+    /// `compute_bind_group_layout` is group 0 of the main pass, the build reads the
+    /// volumes and uniforms from it.
+    pub fn new(
+        device: &wgpu::Device,
+        module: &wgpu::ShaderModule,
+        compute_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let build_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Light cache build bind group layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let sample_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Light cache bind group layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D3,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+
+        let build_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Light cache build pipeline layout"),
+                bind_group_layouts: &[compute_bind_group_layout, &build_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let build_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Light cache build pipeline"),
+            layout: Some(&build_pipeline_layout),
+            module,
+            entry_point: "build_light_cache",
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Light cache sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        // bound even while the cache is off, the main pipeline always has the group
+        let (texture, view, buffer) = create_texture(device, [1, 1, 1]);
+        let (build_bind_group, sample_bind_group) = Self::create_bind_groups(
+            device,
+            &build_bind_group_layout,
+            &sample_bind_group_layout,
+            &view,
+            &buffer,
+            &sampler,
+        );
+
+        Self {
+            build_pipeline,
+            build_bind_group_layout,
+            build_bind_group,
+            sample_bind_group_layout,
+            sample_bind_group,
+            sampler,
+            texture,
+            buffer,
+            built_inputs: Vec::new(),
+            inputs: Vec::new(),
+        }
+    }
+
+    fn create_bind_groups(
+        device: &wgpu::Device,
+        build_layout: &wgpu::BindGroupLayout,
+        sample_layout: &wgpu::BindGroupLayout,
+        view: &wgpu::TextureView,
+        buffer: &wgpu::Buffer,
+        sampler: &wgpu::Sampler,
+    ) -> (wgpu::BindGroup, wgpu::BindGroup) {
+        let build = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Light cache build bind group"),
+            layout: build_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+        let sample = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Light cache bind group"),
+            layout: sample_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        });
+        (build, sample)
+    }
+
+    /// Group 1 of the main pass.
+    pub fn sample_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.sample_bind_group_layout
+    }
+
+    pub fn sample_bind_group(&self) -> &wgpu::BindGroup {
+        &self.sample_bind_group
+    }
+
+    /// Resizes the cache and marks it for a rebuild if the inputs changed since the last
+    /// build. An empty `inputs` turns the cache off.
+    pub fn update(&mut self, device: &wgpu::Device, size: [u32; 3], inputs: Vec<u8>) {
+        let current = [
+            self.texture.width(),
+            self.texture.height(),
+            self.texture.depth_or_array_layers(),
+        ];
+        if !inputs.is_empty() && current != size {
+            let (texture, view, buffer) = create_texture(device, size);
+            (self.build_bind_group, self.sample_bind_group) = Self::create_bind_groups(
+                device,
+                &self.build_bind_group_layout,
+                &self.sample_bind_group_layout,
+                &view,
+                &buffer,
+                &self.sampler,
+            );
+            self.texture = texture;
+            self.buffer = buffer;
+            self.built_inputs.clear();
+        }
+        self.inputs = inputs;
+    }
+
+    /// Records the build if the inputs changed, `compute_bind_group` must hold the
+    /// uniforms the inputs came from.
+    pub fn build(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        compute_bind_group: &wgpu::BindGroup,
+    ) {
+        if self.inputs.is_empty() || self.inputs == self.built_inputs {
+            return;
+        }
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Light cache"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.build_pipeline);
+            compute_pass.set_bind_group(0, compute_bind_group, &[]);
+            compute_pass.set_bind_group(1, &self.build_bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                self.texture.width().div_ceil(WORKGROUP_SIZE),
+                self.texture.height().div_ceil(WORKGROUP_SIZE),
+                self.texture
+                    .depth_or_array_layers()
+                    .div_ceil(WORKGROUP_SIZE),
+            );
+        }
+        encoder.copy_buffer_to_texture(
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row(self.texture.width())),
+                    rows_per_image: Some(self.texture.height()),
+                },
+            },
+            self.texture.as_image_copy(),
+            self.texture.size(),
+        );
+        self.built_inputs = self.inputs.clone();
+    }
+}
@@ -0,0 +1,162 @@
+use crate::editor::settings::Settings;
+use crate::volume_grid::VolumeData;
+use eframe::wgpu;
+use std::sync::Arc;
+
+/// Volumes playing a sequence get a quarter more room than their current frame, so that
+/// slightly larger frames still fit in place.
+const SEQUENCE_HEADROOM: usize = 4;
+
+/// Part of the weights buffer holding the densities of one volume.
+#[derive(Clone)]
+struct Region {
+    /// Start in u32 words.
+    offset: usize,
+    capacity: usize,
+    /// Volume data whose weights the region holds.
+    data: Arc<VolumeData>,
+}
+
+/// Packed densities of all volumes in one storage buffer. Every volume has its own
+/// region, so that switching the frame of a sequence only writes that volume.
+/// Clones share the buffer, a shared buffer is replaced instead of written.
+#[derive(Clone)]
+pub struct WeightsBuffer {
+    buffer: Arc<wgpu::Buffer>,
+    /// One per volume of `Settings::volumes`.
+    regions: Vec<Region>,
+    max_bytes: u64,
+}
+
+/// Largest weights buffer the device can bind.
+pub fn max_weights_bytes(limits: &wgpu::Limits) -> u64 {
+    (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size)
+}
+
+fn create_buffer(device: &wgpu::Device, words: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Weights buffer"),
+        // empty bindings are not allowed
+        size: (words.max(1) * std::mem::size_of::<u32>()) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: true,
+    })
+}
+
+impl WeightsBuffer {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = create_buffer(device, 0);
+        buffer.unmap();
+        Self {
+            buffer: Arc::new(buffer),
+            regions: Vec::new(),
+            max_bytes: max_weights_bytes(&device.limits()),
+        }
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Offsets of the uploaded volumes in `Settings::volumes` order.
+    pub fn offsets(&self) -> impl Iterator<Item = u32> + '_ {
+        self.regions.iter().map(|region| region.offset as u32)
+    }
+
+    /// Writes the weights of the volumes that changed since the last upload.
+    /// Returns true if the buffer had to be replaced and needs to be bound again, or an
+    /// error without any volumes uploaded if they don't fit into one binding.
+    pub fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        settings: &Settings,
+    ) -> Result<bool, String> {
+        let volumes = &settings.volumes;
+        // another renderer may still be reading the old weights
+        let shared = Arc::strong_count(&self.buffer) > 1;
+        let fits = self.regions.len() == volumes.len()
+            && self.regions.iter().zip(volumes).all(|(region, volume)| {
+                if shared {
+                    Arc::ptr_eq(&region.data, &volume.data)
+                } else {
+                    region.capacity >= volume.data.weights.len()
+                }
+            });
+        if fits {
+            for (region, volume) in self.regions.iter_mut().zip(volumes) {
+                if Arc::ptr_eq(&region.data, &volume.data) {
+                    continue;
+                }
+                if !volume.data.weights.is_empty() {
+                    let offset = region.offset * std::mem::size_of::<u32>();
+                    queue.write_buffer(
+                        &self.buffer,
+                        offset as u64,
+                        bytemuck::cast_slice(&volume.data.weights),
+                    );
+                }
+                region.data = volume.data.clone();
+            }
+            return Ok(false);
+        }
+
+        // without the headroom if that is what it takes to fit
+        let regions = [true, false]
+            .into_iter()
+            .map(|headroom| Self::layout(settings, headroom))
+            .find(|regions| Self::bytes(regions) <= self.max_bytes);
+        let Some(regions) = regions else {
+            self.regions.clear();
+            let mib = |bytes: u64| bytes as f64 / (1u64 << 20) as f64;
+            return Err(format!(
+                "Failed to upload the volumes: they need {:.1} MiB, the GPU binds at most {:.1} MiB",
+                mib(Self::bytes(&Self::layout(settings, false))),
+                mib(self.max_bytes)
+            ));
+        };
+        let words = regions
+            .last()
+            .map_or(0, |region| region.offset + region.capacity);
+
+        let buffer = create_buffer(device, words);
+        {
+            let mut mapped = buffer.slice(..).get_mapped_range_mut();
+            let mapped: &mut [u32] = bytemuck::cast_slice_mut(&mut mapped);
+            for region in &regions {
+                let weights = &region.data.weights;
+                mapped[region.offset..region.offset + weights.len()].copy_from_slice(weights);
+            }
+        }
+        buffer.unmap();
+        self.buffer = Arc::new(buffer);
+        self.regions = regions;
+        Ok(true)
+    }
+
+    fn layout(settings: &Settings, headroom: bool) -> Vec<Region> {
+        let mut regions = Vec::with_capacity(settings.volumes.len());
+        let mut words = 0usize;
+        for volume in &settings.volumes {
+            let length = volume.data.weights.len();
+            let capacity = match volume.sequence {
+                Some(_) if headroom => length + length / SEQUENCE_HEADROOM,
+                _ => length,
+            };
+            regions.push(Region {
+                offset: words,
+                capacity,
+                data: volume.data.clone(),
+            });
+            words += capacity;
+        }
+        regions
+    }
+
+    fn bytes(regions: &[Region]) -> u64 {
+        let words = regions
+            .last()
+            .map_or(0, |region| region.offset + region.capacity);
+        (words * std::mem::size_of::<u32>()) as u64
+    }
+}
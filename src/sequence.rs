@@ -0,0 +1,290 @@
+use crate::volume_grid::{LoadOptions, VolumeData};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+const CACHE_SIZE: usize = 8;
+
+/// Numbered files like `smoke.0001.vdb` found next to each other.
+pub struct FrameSequence {
+    prefix: String,
+    suffix: String,
+    padding: usize,
+    /// Sorted frame numbers present on disk.
+    frames: Vec<u32>,
+}
+
+/// Splits a file name around its last run of digits.
+fn split_frame_number(file_name: &str) -> Option<(&str, &str, &str)> {
+    let end = file_name.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = file_name[..end]
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |i| i + 1);
+    Some((
+        &file_name[..start],
+        &file_name[start..end],
+        &file_name[end..],
+    ))
+}
+
+impl FrameSequence {
+    /// Looks for other frames of the same sequence, returns `None` for a single file.
+    pub fn detect(path: &str) -> Option<Self> {
+        let path = Path::new(path);
+        let directory = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        let file_name = path.file_name()?.to_str()?;
+        let (name_prefix, digits, suffix) = split_frame_number(file_name)?;
+
+        let mut frames: Vec<u32> = std::fs::read_dir(directory.unwrap_or(Path::new(".")))
+            .ok()?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter_map(|name| {
+                let number = name.strip_prefix(name_prefix)?.strip_suffix(suffix)?;
+                if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                number.parse().ok()
+            })
+            .collect();
+        frames.sort_unstable();
+        frames.dedup();
+
+        if frames.len() < 2 {
+            return None;
+        }
+
+        let prefix = match directory {
+            Some(dir) => dir.join(name_prefix).display().to_string(),
+            None => name_prefix.to_string(),
+        };
+
+        Some(Self {
+            prefix,
+            suffix: suffix.to_string(),
+            padding: digits.len(),
+            frames,
+        })
+    }
+
+    pub fn first_frame(&self) -> u32 {
+        self.frames[0]
+    }
+
+    pub fn last_frame(&self) -> u32 {
+        self.frames[self.frames.len() - 1]
+    }
+
+    /// Path of the closest frame at or before the requested one.
+    pub fn path(&self, frame: u32) -> String {
+        let index = self.frames.partition_point(|&f| f <= frame).max(1) - 1;
+        format!(
+            "{}{:0width$}{}",
+            self.prefix,
+            self.frames[index],
+            self.suffix,
+            width = self.padding
+        )
+    }
+}
+
+/// Loads frames on a worker thread and keeps the most recently used ones in memory.
+pub struct SequenceLoader {
+    requests: Sender<(String, LoadOptions)>,
+    results: Receiver<(String, LoadOptions, Result<VolumeData, String>)>,
+    cache: HashMap<String, Arc<VolumeData>>,
+    /// Cached paths, least recently used first.
+    usage: VecDeque<String>,
+    pending: HashSet<String>,
+    /// Frames that failed to load with the options they were requested with, they are not
+    /// requested again until the options change.
+    failed: HashMap<String, LoadOptions>,
+}
+
+impl SequenceLoader {
+    pub fn new() -> Self {
+        let (requests, worker_requests) = mpsc::channel::<(String, LoadOptions)>();
+        let (worker_results, results) = mpsc::channel();
+
+        thread::spawn(move || {
+            for (path, options) in worker_requests {
+                let result = VolumeData::load(&path, &options).map_err(|err| err.to_string());
+                if worker_results.send((path, options, result)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            requests,
+            results,
+            cache: HashMap::new(),
+            usage: VecDeque::new(),
+            pending: HashSet::new(),
+            failed: HashMap::new(),
+        }
+    }
+
+    /// Moves finished loads into the cache.
+    pub fn poll(&mut self) {
+        while let Ok((path, options, result)) = self.results.try_recv() {
+            self.pending.remove(&path);
+            match result {
+                Ok(data) => {
+                    self.cache.insert(path.clone(), Arc::new(data));
+                    self.touch(&path);
+                }
+                Err(err) => {
+                    log::error!("Failed to load {}: {}", path, err);
+                    self.failed.insert(path, options);
+                }
+            }
+        }
+
+        while self.usage.len() > CACHE_SIZE {
+            if let Some(path) = self.usage.pop_front() {
+                self.cache.remove(&path);
+            }
+        }
+    }
+
+    fn touch(&mut self, path: &str) {
+        self.usage.retain(|cached| cached != path);
+        self.usage.push_back(path.to_string());
+    }
+
+    /// Returns the frame if it is cached, otherwise queues it for loading.
+    pub fn get(&mut self, path: &str, options: &LoadOptions) -> Option<Arc<VolumeData>> {
+        self.request(path, options);
+        let data = self.cache.get(path).cloned()?;
+        self.touch(path);
+        Some(data)
+    }
+
+    /// Frames requested and not read yet.
+    pub fn is_loading(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// The frame could not be read with these options, playback skips it.
+    pub fn has_failed(&self, path: &str, options: &LoadOptions) -> bool {
+        self.failed
+            .get(path)
+            .is_some_and(|failed| !failed.repacks(options))
+    }
+
+    pub fn request(&mut self, path: &str, options: &LoadOptions) {
+        // frames packed with outdated options are loaded again
+        if self
+            .cache
+            .get(path)
+            .is_some_and(|data| data.options.repacks(options))
+        {
+            self.cache.remove(path);
+            self.usage.retain(|cached| cached != path);
+        }
+        if self.cache.contains_key(path)
+            || self.pending.contains(path)
+            || self.has_failed(path, options)
+        {
+            return;
+        }
+        if self.requests.send((path.to_string(), *options)).is_ok() {
+            self.pending.insert(path.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// Empty files with the given names in a fresh directory.
+    fn create_files(test: &str, names: &[&str]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("render-sequence-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        for name in names {
+            fs::write(directory.join(name), []).unwrap();
+        }
+        directory
+    }
+
+    fn detect(directory: &Path, name: &str) -> Option<FrameSequence> {
+        FrameSequence::detect(&directory.join(name).display().to_string())
+    }
+
+    #[test]
+    fn splits_around_last_digits() {
+        assert_eq!(
+            split_frame_number("smoke_v2.0010.vdb"),
+            Some(("smoke_v2.", "0010", ".vdb"))
+        );
+        assert_eq!(split_frame_number("42.vdb"), Some(("", "42", ".vdb")));
+        assert_eq!(split_frame_number("frame7"), Some(("frame", "7", "")));
+        assert_eq!(split_frame_number("smoke.vdb"), None);
+    }
+
+    #[test]
+    fn detects_padded_frames() {
+        let directory = create_files(
+            "padded",
+            &[
+                "smoke.0001.vdb",
+                "smoke.0002.vdb",
+                "smoke.0003.vdb",
+                "other.0004.vdb",
+            ],
+        );
+        let sequence = detect(&directory, "smoke.0002.vdb").unwrap();
+        assert_eq!(sequence.first_frame(), 1);
+        assert_eq!(sequence.last_frame(), 3);
+        let expected = directory.join("smoke.0003.vdb").display().to_string();
+        assert_eq!(sequence.path(3), expected);
+    }
+
+    #[test]
+    fn single_file_is_not_a_sequence() {
+        let directory = create_files("single", &["smoke.0001.vdb", "smoke.vdb"]);
+        assert!(detect(&directory, "smoke.0001.vdb").is_none());
+        assert!(detect(&directory, "smoke.vdb").is_none());
+    }
+
+    #[test]
+    fn missing_frames_hold_the_previous_one() {
+        let directory = create_files("missing", &["fire.1.vdb", "fire.2.vdb", "fire.5.vdb"]);
+        let sequence = detect(&directory, "fire.1.vdb").unwrap();
+        let path = |frame: u32| {
+            directory
+                .join(format!("fire.{}.vdb", frame))
+                .display()
+                .to_string()
+        };
+        assert_eq!(sequence.path(0), path(1));
+        assert_eq!(sequence.path(3), path(2));
+        assert_eq!(sequence.path(4), path(2));
+        assert_eq!(sequence.path(5), path(5));
+        assert_eq!(sequence.path(100), path(5));
+    }
+
+    #[test]
+    fn frames_wider_than_the_padding() {
+        let directory = create_files(
+            "wide",
+            &[
+                "cloud_098.vdb",
+                "cloud_099.vdb",
+                "cloud_100.vdb",
+                "cloud_1000.vdb",
+            ],
+        );
+        let sequence = detect(&directory, "cloud_099.vdb").unwrap();
+        assert_eq!(sequence.last_frame(), 1000);
+        let expected = directory.join("cloud_1000.vdb").display().to_string();
+        assert_eq!(sequence.path(1000), expected);
+    }
+}
@@ -45,8 +45,182 @@ var r_color: texture_2d<f32>;
 @group(0)
 @binding(1)
 var r_sampler: sampler;
+// AOVs laid out as in compute.wgsl
+@group(0)
+@binding(3)
+var r_lighting: texture_2d<f32>;
+@group(0)
+@binding(4)
+var r_emission: texture_2d<f32>;
+@group(0)
+@binding(5)
+var r_depth: texture_2d<f32>;
+
+// must match ToneMapOperator::as_u32
+const TONE_MAP_CLAMP: u32 = 0u;
+const TONE_MAP_REINHARD: u32 = 1u;
+const TONE_MAP_ACES: u32 = 2u;
+const TONE_MAP_AGX: u32 = 3u;
+
+// must match OutputPass::as_u32, passes from PASS_TRANSMITTANCE on are scalar data
+const PASS_BEAUTY: u32 = 0u;
+const PASS_DIRECT: u32 = 1u;
+const PASS_EMISSION: u32 = 2u;
+const PASS_TRANSMITTANCE: u32 = 3u;
+const PASS_FIRST_DEPTH: u32 = 4u;
+const PASS_WEIGHTED_DEPTH: u32 = 5u;
+const PASS_DENSITY_INTEGRAL: u32 = 6u;
+
+// log2 range of the AgX base curve, its upper end is the white point it was made for
+const AGX_MIN_EV: f32 = -12.47393;
+const AGX_MAX_EV: f32 = 4.026069;
+
+struct ToneMapping {
+    // linear multiplier, 2^exposure
+    exposure: f32,
+    // scene value mapped to display white
+    white_point: f32,
+    tone_operator: u32,
+    // set when the target format doesn't encode sRGB on write
+    encode_srgb: u32,
+    // outputs straight alpha instead of compositing transparent pixels over a checkerboard
+    keep_alpha: u32,
+    display_pass: u32,
+    // camera distances of the scene bounds, depths are shown near white to far black
+    depth_near: f32,
+    depth_far: f32,
+};
+
+@group(0)
+@binding(2)
+var<uniform> tone_mapping: ToneMapping;
+
+// extended Reinhard that reaches 1 at the white point
+fn reinhard(color: vec3f, white: f32) -> vec3f {
+    return color * (1.0 + color / (white * white)) / (1.0 + color);
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces_curve(x: vec3f) -> vec3f {
+    return (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+}
+
+fn aces(color: vec3f, white: f32) -> vec3f {
+    return aces_curve(color) / aces_curve(vec3f(white));
+}
+
+// polynomial approximation of the AgX base contrast curve
+fn agx_contrast(x: vec3f) -> vec3f {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2
+        + 0.1191 * x - 0.00232;
+}
+
+fn agx(color: vec3f, white: f32) -> vec3f {
+    let inset = mat3x3f(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104
+    );
+    let outset = mat3x3f(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116
+    );
+    let scaled = color * exp2(AGX_MAX_EV) / white;
+    var log_color = clamp(log2(max(inset * scaled, vec3f(1e-10))), vec3f(AGX_MIN_EV), vec3f(AGX_MAX_EV));
+    log_color = (log_color - AGX_MIN_EV) / (AGX_MAX_EV - AGX_MIN_EV);
+    // the curve outputs display values, decoded back to linear for the common sRGB encoding
+    let display = outset * agx_contrast(log_color);
+    return pow(clamp(display, vec3f(0.0), vec3f(1.0)), vec3f(2.2));
+}
+
+fn linear_to_srgb(color: vec3f) -> vec3f {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3f(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3f(0.0031308));
+}
+
+const CHECKER_SIZE: f32 = 8.0;
+const CHECKER_DARK: f32 = 0.2;
+const CHECKER_LIGHT: f32 = 0.3;
+
+fn depth_display(depth: f32) -> f32 {
+    let range = max(tone_mapping.depth_far - tone_mapping.depth_near, 1e-6);
+    let value = 1.0 - clamp((depth - tone_mapping.depth_near) / range, 0.0, 1.0);
+    // rays that hit nothing have zero depth
+    return select(0.0, value, depth > 0.0);
+}
+
+// scalar passes are shown as they are, without tone mapping
+fn scalar_pass(tex_coords: vec2f) -> f32 {
+    switch tone_mapping.display_pass {
+        case PASS_TRANSMITTANCE: {
+            return textureSample(r_lighting, r_sampler, tex_coords).a;
+        }
+        case PASS_FIRST_DEPTH: {
+            return depth_display(textureSample(r_depth, r_sampler, tex_coords).r);
+        }
+        case PASS_WEIGHTED_DEPTH: {
+            return depth_display(textureSample(r_depth, r_sampler, tex_coords).g);
+        }
+        default: {
+            return 1.0 - exp(-textureSample(r_emission, r_sampler, tex_coords).a);
+        }
+    }
+}
 
 @fragment
 fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(r_color, r_sampler, vertex.tex_coords);
+    var premultiplied = textureSample(r_color, r_sampler, vertex.tex_coords);
+    let lighting = textureSample(r_lighting, r_sampler, vertex.tex_coords);
+    let emission = textureSample(r_emission, r_sampler, vertex.tex_coords);
+    switch tone_mapping.display_pass {
+        case PASS_BEAUTY: {}
+        case PASS_DIRECT: {
+            premultiplied = vec4f(lighting.rgb, 1.0);
+        }
+        case PASS_EMISSION: {
+            premultiplied = vec4f(emission.rgb, 1.0);
+        }
+        default: {
+            return vec4f(vec3f(scalar_pass(vertex.tex_coords)), 1.0);
+        }
+    }
+    let alpha = clamp(premultiplied.a, 0.0, 1.0);
+    // the color of the covered part is tone mapped, not the one faded by coverage
+    let straight = select(vec3f(0.0), premultiplied.rgb / alpha, alpha > 0.0);
+    let hdr = max(straight, vec3f(0.0)) * tone_mapping.exposure;
+    let white = max(tone_mapping.white_point, 1e-3);
+
+    var color: vec3f;
+    switch tone_mapping.tone_operator {
+        case TONE_MAP_REINHARD: {
+            color = reinhard(hdr, white);
+        }
+        case TONE_MAP_ACES: {
+            color = aces(hdr, white);
+        }
+        case TONE_MAP_AGX: {
+            color = agx(hdr, white);
+        }
+        default: {
+            color = hdr;
+        }
+    }
+    color = clamp(color, vec3f(0.0), vec3f(1.0));
+
+    var output_alpha = alpha;
+    if tone_mapping.keep_alpha == 0u {
+        let cell = vec2u(vertex.position.xy / CHECKER_SIZE);
+        let checker = select(CHECKER_DARK, CHECKER_LIGHT, ((cell.x + cell.y) & 1u) == 0u);
+        color = mix(vec3f(checker), color, alpha);
+        output_alpha = 1.0;
+    }
+
+    if tone_mapping.encode_srgb != 0u {
+        color = linear_to_srgb(color);
+    }
+    return vec4f(color, output_alpha);
 }
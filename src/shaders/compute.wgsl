@@ -1,8 +1,8 @@
 @group(0) @binding(0)
-var output_texture: texture_storage_2d<rgba8unorm, write>;
+var output_texture: texture_storage_2d<rgba16float, write>;
 
 @group(0) @binding(1)
-var<storage, read> volume_grid: VolumeGridStatic;
+var<storage, read> volumes: array<VolumeInstance, MAX_VOLUMES>;
 
 @group(0) @binding(2)
 var<storage, read> weights: array<u32>;
@@ -10,27 +10,110 @@ var<storage, read> weights: array<u32>;
 @group(0) @binding(3)
 var<uniform> uniforms: Uniforms;
 
-const width = 800.0;
-const height = 600.0;
+// arbitrary output variables, see `Aovs`
+// rgb is the direct in-scattering, a the transmittance
+@group(0) @binding(4)
+var lighting_texture: texture_storage_2d<rgba16float, write>;
+// rgb is the emission, a the density integral
+@group(0) @binding(5)
+var emission_texture: texture_storage_2d<rgba16float, write>;
+// r is the first scatter depth, g the transmittance weighted depth
+@group(0) @binding(6)
+var depth_texture: texture_storage_2d<rgba16float, write>;
+
+@group(0) @binding(7)
+var<storage, read_write> ray_stats: RayStats;
+
+// transmittance towards the light over the scene bbox in r, written by build_light_cache
+// into a buffer that is copied to the texture read by the main pass, see `LightCache`
+@group(1) @binding(0)
+var<storage, read_write> light_cache_output: array<vec2u>;
+@group(1) @binding(1)
+var light_cache: texture_3d<f32>;
+@group(1) @binding(2)
+var light_cache_sampler: sampler;
 
 const INF = 99999.0;
 
-const ratio = width / height;
-
 const PI: f32 = 3.14159265358979323846;
 
 const BASE_WEIGHT: f32 = 0.1;
 
+const RENDER_MODE_PHYSICALLY_BASED: u32 = 0u;
+const RENDER_MODE_MAXIMUM_INTENSITY: u32 = 1u;
+const RENDER_MODE_MINIMUM_INTENSITY: u32 = 2u;
+const RENDER_MODE_AVERAGE_INTENSITY: u32 = 3u;
+const RENDER_MODE_FIRST_HIT: u32 = 4u;
+const RENDER_MODE_ISOSURFACE: u32 = 5u;
+
+// must match Quantization::as_u32
+const QUANTIZATION_LINEAR_8: u32 = 0u;
+const QUANTIZATION_SQRT_8: u32 = 1u;
+const QUANTIZATION_HALF_16: u32 = 2u;
+const QUANTIZATION_FLOAT_32: u32 = 3u;
+
+// texels per row of light_cache_output, rows of buffer to texture copies are aligned to
+// 256 bytes
+const LIGHT_CACHE_ROW_ALIGNMENT: u32 = 32u;
+
+const MAX_CLIP_PLANES: u32 = 4u;
+const MAX_VOLUMES: u32 = 8u;
+
+const ISO_BISECTION_STEPS: u32 = 8u;
+const ISO_ALBEDO = vec3f(0.8);
+const ISO_AMBIENT: f32 = 0.15;
+const ISO_SHININESS: f32 = 32.0;
+
+// bounds the adaptive march, refinements included
+const MAX_ADAPTIVE_STEPS: u32 = 4096u;
+// relative change of the extinction between samples that is refined with shorter steps
+const EDGE_THRESHOLD: f32 = 0.5;
+// optical thickness an adaptive step may cover in thin regions
+const TARGET_OPTICAL_THICKNESS: f32 = 0.05;
+
 
 struct Uniforms {
     color: vec4f,
     camera_to_world: mat4x4f,
     light_dir: vec4f,
     light_col: vec4f,
-    absorption: f32,
-    scattering: f32,
-    g: f32,
+    // measured in voxels of the finest visible volume
     step_size: f32,
+    // smallest voxel size of the visible volumes in world units
+    voxel_size: f32,
+    volume_count: u32,
+    render_mode: u32,
+    iso_value: f32,
+    cap_clip_planes: u32,
+    density_scale: f32,
+    density_gamma: f32,
+    // xyz is the unit normal, w is the plane distance; zero normal means disabled
+    clip_planes: array<vec4f, MAX_CLIP_PLANES>,
+    density_contrast: f32,
+    density_invert: u32,
+    // outputs premultiplied color with 1 - transmittance as alpha instead of the background
+    transparent_background: u32,
+    // counts the rays into ray_stats
+    ray_stats: u32,
+    // subpixel position of the ray, jittered when accumulating several samples
+    pixel_offset: vec2f,
+    // the physically based mode adapts its steps, see `march_adaptive`
+    adaptive_step: u32,
+    // largest adaptive step as a multiple of step_size
+    max_step_scale: f32,
+    // smallest adaptive step in pixel footprints
+    step_footprint: f32,
+    // step towards the light in voxels when adapting
+    light_step: f32,
+    max_light_steps: u32,
+    // randomly offsets the first adaptive sample of every pixel
+    jitter: u32,
+    // bounds of the light cache, the scene bbox
+    light_cache_min: vec4f,
+    light_cache_max: vec4f,
+    light_cache_size: vec3u,
+    // lights the samples from the light cache instead of marching towards the light
+    light_cache: u32,
 }
 
 struct Ray {
@@ -46,7 +129,34 @@ struct Aabb {
 struct VolumeGridStatic {
     size: vec4u,
     shift: vec4i,
+    // world space bounds
     bbox: Aabb,
+    world_to_index: mat4x4f,
+    // xyz is the voxel size in world units, w is its smallest component
+    voxel_size: vec4f,
+}
+
+struct VolumeInstance {
+    // already placed by the instance transform
+    grid: VolumeGridStatic,
+    // absorption, scattering, g
+    material: vec4f,
+    weights_offset: u32,
+    quantization: u32,
+    // scales the stored densities, the interior density of level sets
+    density_scale: f32,
+    // rgb is the emitted radiance per unit density
+    emission: vec4f,
+}
+
+// scattering and extinction coefficients of all volumes overlapping at a point
+struct Medium {
+    extinction: f32,
+    scattering: f32,
+    // scattering weighted average of the volumes' g
+    g: f32,
+    density: f32,
+    emission: vec3f,
 }
 
 struct Sphere {
@@ -64,6 +174,46 @@ struct RayRecord {
     color: vec3f,
 }
 
+// passes written next to the beauty image, only filled by the physically based mode
+struct Aovs {
+    direct: vec3f,
+    emission: vec3f,
+    // ray distance of the first sample with any extinction, 0 if there is none
+    first_depth: f32,
+    // ray distance averaged by the opacity each sample adds
+    weighted_depth: f32,
+    density_integral: f32,
+}
+
+var<private> aovs: Aovs;
+
+// totals over the frame for the performance pane, must match RayStats in profiler.rs;
+// sample counts are 64 bit as low and high words
+struct RayStats {
+    rays: atomic<u32>,
+    samples_low: atomic<u32>,
+    samples_high: atomic<u32>,
+    light_samples_low: atomic<u32>,
+    light_samples_high: atomic<u32>,
+    early_terminated: atomic<u32>,
+}
+
+// totals of the workgroup, added to ray_stats once per workgroup
+var<workgroup> group_rays: atomic<u32>;
+var<workgroup> group_samples: atomic<u32>;
+var<workgroup> group_light_samples: atomic<u32>;
+var<workgroup> group_early_terminated: atomic<u32>;
+
+// march statistics of the current ray
+struct RayCounters {
+    samples: u32,
+    light_samples: u32,
+    // stopped before leaving the volume, by opacity or by a hit
+    early_terminated: bool,
+}
+
+var<private> counters: RayCounters;
+
 
 fn ray_at(ray: Ray, t: f32) -> vec3f {
     return ray.origin.xyz + ray.direction.xyz * t;
@@ -104,96 +254,572 @@ fn hit_aabb(aabb: Aabb, ray: Ray) -> Interval {
     return interval;
 }
 
-fn get_weight(pos: vec3f) -> f32 {
+// keeps the part of the interval behind every enabled plane: dot(normal, p) <= distance
+fn clip_interval(ray: Ray, interval: Interval) -> Interval {
+    var clipped = interval;
+
+    for (var i = 0u; i < MAX_CLIP_PLANES; i++) {
+        let plane = uniforms.clip_planes[i];
+        if all(plane.xyz == vec3f(0.0)) {
+            continue;
+        }
+
+        let dist = dot(plane.xyz, ray.origin.xyz) - plane.w;
+        let denom = dot(plane.xyz, ray.direction.xyz);
+
+        if denom == 0.0 {
+            if dist > 0.0 {
+                return Interval(0.0, 0.0);
+            }
+            continue;
+        }
+
+        let t = -dist / denom;
+        if denom > 0.0 {
+            clipped.end = min(clipped.end, t);
+        } else {
+            clipped.start = max(clipped.start, t);
+        }
+
+        if clipped.start >= clipped.end {
+            return Interval(0.0, 0.0);
+        }
+    }
+
+    return clipped;
+}
+
+// interval covering the bboxes of all visible volumes
+fn hit_scene(ray: Ray) -> Interval {
+    var interval = Interval(INF, 0.0);
+
+    for (var i = 0u; i < uniforms.volume_count; i++) {
+        let hit = hit_aabb(volumes[i].grid.bbox, ray);
+        if hit.start < hit.end {
+            interval.start = min(interval.start, hit.start);
+            interval.end = max(interval.end, hit.end);
+        }
+    }
+
+    if interval.start >= interval.end {
+        return Interval(0.0, 0.0);
+    }
+    return interval;
+}
+
+fn hit_volume(ray: Ray) -> Interval {
+    return clip_interval(ray, hit_scene(ray));
+}
+
+fn density_colormap(weight: f32) -> vec3f {
+    let w = clamp(weight, 0.0, 1.0);
+    return clamp(vec3f(w * 3.0, w * 3.0 - 1.0, w * 3.0 - 2.0), vec3f(0.0), vec3f(1.0));
+}
+
+// colours the density on the cut face if the ray enters the volume through a clipping plane
+fn get_cap(ray: Ray) -> RayRecord {
+    let box_interval = hit_scene(ray);
+    let interval = clip_interval(ray, box_interval);
+    if interval.start >= interval.end || interval.start <= box_interval.start {
+        return RayRecord(1.0, vec3f(0.0));
+    }
+
+    let weight = get_weight(ray_at(ray, interval.start + 0.01 * uniforms.voxel_size));
+    if weight <= 0.0 {
+        return RayRecord(1.0, vec3f(0.0));
+    }
+
+    return RayRecord(0.0, density_colormap(weight));
+}
+
+// the step size uniform is measured in voxels
+fn world_step_size() -> f32 {
+    return uniforms.step_size * uniforms.voxel_size;
+}
+
+// user remap of the stored density, applied without reloading the grid
+fn remap_density(weight: f32) -> f32 {
+    var density = weight;
+    if uniforms.density_invert != 0u {
+        density = 1.0 - density;
+    }
+    // s-curve around 0.5 that keeps empty space empty
+    if density > 0.0 && density < 1.0 {
+        let low = pow(density, uniforms.density_contrast);
+        let high = pow(1.0 - density, uniforms.density_contrast);
+        density = low / (low + high);
+    }
+    // keeps the sign of raw negative values
+    density = sign(density) * pow(abs(density), uniforms.density_gamma);
+    return density * uniforms.density_scale;
+}
+
+fn get_instance_weight(instance: u32, world_pos: vec3f) -> f32 {
+    let pos = (volumes[instance].grid.world_to_index * vec4f(world_pos, 1.0)).xyz;
+    if any(pos < vec3f(0.0)) {
+        return 0.0;
+    }
     let pos3u = vec3u(u32(floor(pos.x)), u32(floor(pos.y)), u32(floor(pos.z)));
-    let size = volume_grid.size.xyz;
+    let size = volumes[instance].grid.size.xyz;
 
-    if any(pos3u < vec3u(0u) || pos3u >= size) {
+    if any(pos3u >= size) {
         return 0.0;
     }
 
     let linear_index = pos3u.z + pos3u.y * size.z + pos3u.x * size.z * size.y;
-    let block_index = linear_index / 4u;
-    let num_index = linear_index % 4u;
-    let current_block = weights[block_index];
+    // scaled after the remap, which expects stored densities in [0, 1]
+    return remap_density(decode_weight(instance, linear_index)) * volumes[instance].density_scale;
+}
+
+fn decode_weight(instance: u32, linear_index: u32) -> f32 {
+    let quantization = volumes[instance].quantization;
+    let bits = select(8u, 16u, quantization == QUANTIZATION_HALF_16);
+    if quantization == QUANTIZATION_FLOAT_32 {
+        return bitcast<f32>(weights[volumes[instance].weights_offset + linear_index]);
+    }
+
+    let values_per_block = 32u / bits;
+    let block_index = linear_index / values_per_block;
+    let num_index = linear_index % values_per_block;
+    let current_block = weights[volumes[instance].weights_offset + block_index];
+    let code = (current_block >> (32u - bits * (num_index + 1u))) & ((1u << bits) - 1u);
+
+    switch quantization {
+        case QUANTIZATION_SQRT_8: {
+            let value = f32(code) / 255.0;
+            return value * value;
+        }
+        case QUANTIZATION_HALF_16: {
+            return unpack2x16float(code).x;
+        }
+        default: {
+            return f32(code) / 255.0;
+        }
+    }
+}
+
+// summed density of all volumes, used by the visualisation modes
+fn get_weight(world_pos: vec3f) -> f32 {
+    var weight = 0.0;
+    for (var i = 0u; i < uniforms.volume_count; i++) {
+        weight += get_instance_weight(i, world_pos);
+    }
+    return weight;
+}
+
+fn get_medium(world_pos: vec3f) -> Medium {
+    var medium = Medium(0.0, 0.0, 0.0, 0.0, vec3f(0.0));
+
+    for (var i = 0u; i < uniforms.volume_count; i++) {
+        let weight = get_instance_weight(i, world_pos);
+        if weight > 0.0 {
+            let material = volumes[i].material;
+            let scattering = weight * material.y;
+            medium.extinction += weight * (material.x + material.y);
+            medium.scattering += scattering;
+            medium.g += scattering * material.z;
+            medium.density += weight;
+            medium.emission += weight * volumes[i].emission.xyz;
+        }
+    }
+
+    if medium.scattering > 0.0 {
+        medium.g /= medium.scattering;
+    }
+    return medium;
+}
+
+// transmittance towards the light from the start of the light ray
+fn light_attenuation(ray_light: Ray, end: f32, step_size: f32) -> f32 {
+    var light_step = step_size;
+    var ns_light = u32(floor((end / light_step) + 0.5));
+    if uniforms.adaptive_step != 0u {
+        // the light step is set on its own and stretched to stay within the cap
+        light_step = uniforms.light_step * uniforms.voxel_size;
+        ns_light = u32(ceil(end / light_step));
+        if ns_light > uniforms.max_light_steps {
+            ns_light = uniforms.max_light_steps;
+            light_step = end / f32(ns_light);
+        }
+    }
+
+    var density_light = 0.0;
+
+    for (var nl = 0u; nl < ns_light; nl++) {
+        let t_light = min(f32(nl) * light_step, end);
+        let sample_pos_light = ray_at(ray_light, t_light);
+        density_light += get_medium(sample_pos_light).extinction;
+        counters.light_samples += 1u;
+    }
+
+    return exp(-density_light * light_step);
+}
+
+fn sample_light_cache(pos: vec3f) -> f32 {
+    let uvw = (pos - uniforms.light_cache_min.xyz) / (uniforms.light_cache_max.xyz - uniforms.light_cache_min.xyz);
+    counters.light_samples += 1u;
+    return textureSampleLevel(light_cache, light_cache_sampler, uvw, 0.0).r;
+}
+
+// adds the light scattered and emitted over one step of length dt around the sample at t
+fn integrate_step(state: ptr<function, RayRecord>, ray: Ray, t: f32, dt: f32, medium: Medium) {
+    aovs.density_integral += medium.density * dt;
+    if medium.extinction <= 0.0 {
+        return;
+    }
+
+    if aovs.first_depth == 0.0 {
+        aovs.first_depth = t;
+    }
+    // emission of the step is only attenuated by the medium in front of it
+    aovs.emission += (*state).transparency * dt * medium.emission;
+    let sample_transparency = exp(-dt * medium.extinction);
+    aovs.weighted_depth += t * (*state).transparency * (1.0 - sample_transparency);
+    (*state).transparency *= sample_transparency;
+
+    //light
+    var lit = true;
+    var light_ray_attenutation = 1.0;
+    if uniforms.light_cache != 0u {
+        light_ray_attenutation = sample_light_cache(ray_at(ray, t));
+    } else {
+        let ray_light = create_ray(ray_at(ray, t), uniforms.light_dir.xyz);
+        let interval_light = hit_volume(ray_light);
+        lit = interval_light.start < interval_light.end;
+        if lit {
+            light_ray_attenutation = light_attenuation(ray_light, interval_light.end, world_step_size());
+        }
+    }
+    if lit {
+        let cos_theta = dot(ray.direction, -uniforms.light_dir) / (length(ray.direction) * length(uniforms.light_dir));
+        (*state).color += uniforms.light_col.xyz * light_ray_attenutation * (*state).transparency * dt * medium.scattering * phase(cos_theta, medium.g);
+    }
+}
+
+// samples evenly spaced by the ray marching step
+fn march_uniform(state: ptr<function, RayRecord>, ray: Ray, interval: Interval) {
+    let step_size = world_step_size();
+    let ns = u32(floor(((interval.end - interval.start) / step_size) + 0.5));
+
+    for (var n = 0u; n < ns; n++) {
+        if (*state).transparency <= 0.005 {
+            counters.early_terminated = true;
+            break;
+        }
+
+        let t = interval.start + step_size * (f32(n) + 0.5);
+        let medium = get_medium(ray_at(ray, t));
+        counters.samples += 1u;
+        integrate_step(state, ray, t, step_size, medium);
+    }
+}
+
+// steps grow in thin and distant regions, up to the max step scale, and are taken again
+// at half length where the density changes sharply
+fn march_adaptive(state: ptr<function, RayRecord>, ray: Ray, interval: Interval, jitter: f32) {
+    let base_step = world_step_size();
+    let min_step = 0.5 * base_step;
+    let max_step = base_step * max(uniforms.max_step_scale, 1.0);
+    // world size of a pixel one unit in front of the camera
+    let pixel_footprint = 2.0 / f32(textureDimensions(output_texture).y);
+
+    var t = interval.start;
+    if uniforms.jitter != 0u {
+        t += jitter * base_step;
+    }
+    var step_size = base_step;
+    var previous_extinction = 0.0;
+
+    for (var n = 0u; n < MAX_ADAPTIVE_STEPS && t < interval.end; n++) {
+        if (*state).transparency <= 0.005 {
+            counters.early_terminated = true;
+            break;
+        }
+
+        let dt = min(step_size, interval.end - t);
+        let sample_t = t + 0.5 * dt;
+        let medium = get_medium(ray_at(ray, sample_t));
+        counters.samples += 1u;
+
+        let change = abs(medium.extinction - previous_extinction);
+        if step_size > min_step && change > EDGE_THRESHOLD * max(medium.extinction, previous_extinction) {
+            step_size = max(0.5 * step_size, min_step);
+            continue;
+        }
 
-    let weigth = (current_block >> (24u - num_index * 8u) & 255u);
+        integrate_step(state, ray, sample_t, dt, medium);
+        previous_extinction = medium.extinction;
+        t += dt;
 
-    return f32(weigth) / f32(255u);
+        var next_step = max_step;
+        if medium.extinction > 0.0 {
+            next_step = TARGET_OPTICAL_THICKNESS / medium.extinction;
+        }
+        // distant samples needn't be finer than the pixels showing them
+        next_step = max(next_step, uniforms.step_footprint * pixel_footprint * t);
+        step_size = min(clamp(next_step, base_step, max_step), 2.0 * step_size);
+    }
 }
 
-fn get_color(ray: Ray) -> RayRecord {
-    let interval = hit_aabb(volume_grid.bbox, ray);
+fn get_color(ray: Ray, jitter: f32) -> RayRecord {
+    let interval = hit_volume(ray);
     if interval.start >= interval.end {
         return RayRecord(1.0, vec3f(0.0));
     }
 
-    var step_size = uniforms.step_size;
-    var sigma = uniforms.scattering + uniforms.absorption;
+    var state = RayRecord(1.0, vec3f(0.0));
+    if uniforms.adaptive_step != 0u {
+        march_adaptive(&state, ray, interval, jitter);
+    } else {
+        march_uniform(&state, ray, interval);
+    }
+
+    aovs.direct = state.color;
+    if state.transparency < 1.0 {
+        aovs.weighted_depth /= 1.0 - state.transparency;
+    }
+    return RayRecord(state.transparency, state.color + aovs.emission);
+}
+
+// classic projection modes: the result is an intensity in [0, 1]
+// that is composited over the background like an opacity
+fn get_projection(ray: Ray) -> RayRecord {
+    let interval = hit_volume(ray);
+    if interval.start >= interval.end {
+        return RayRecord(1.0, vec3f(0.0));
+    }
 
-    var transparency = 1.0;
-    var result = vec3f(0.0);
+    let step_size = world_step_size();
     let ns = u32(floor(((interval.end - interval.start) / step_size) + 0.5));
+    if ns == 0u {
+        return RayRecord(1.0, vec3f(0.0));
+    }
+
+    var max_weight = 0.0;
+    var min_weight = 1.0;
+    var sum_weight = 0.0;
+    var intensity = 0.0;
 
     for (var n = 0u; n < ns; n++) {
-        if transparency <= 0.005 {
-            break;
+        let t = interval.start + step_size * (f32(n) + 0.5);
+        let sample_weight = get_weight(ray_at(ray, t));
+        counters.samples += 1u;
+
+        if uniforms.render_mode == RENDER_MODE_FIRST_HIT {
+            if sample_weight >= uniforms.iso_value {
+                // depth cue: closer hits are brighter
+                intensity = 1.0 - f32(n) / f32(ns);
+                counters.early_terminated = true;
+                break;
+            }
+            continue;
+        }
+
+        max_weight = max(max_weight, sample_weight);
+        min_weight = min(min_weight, sample_weight);
+        sum_weight += sample_weight;
+    }
+
+    switch uniforms.render_mode {
+        case RENDER_MODE_MAXIMUM_INTENSITY: {
+            intensity = max_weight;
         }
+        case RENDER_MODE_MINIMUM_INTENSITY: {
+            intensity = min_weight;
+        }
+        case RENDER_MODE_AVERAGE_INTENSITY: {
+            intensity = sum_weight / f32(ns);
+        }
+        default: {}
+    }
 
+    return RayRecord(1.0 - intensity, vec3f(intensity));
+}
+
+fn get_gradient(pos: vec3f) -> vec3f {
+    let h = vec3f(uniforms.voxel_size);
+    let dx = vec3f(h.x, 0.0, 0.0);
+    let dy = vec3f(0.0, h.y, 0.0);
+    let dz = vec3f(0.0, 0.0, h.z);
+    return vec3f(
+        (get_weight(pos + dx) - get_weight(pos - dx)) / h.x,
+        (get_weight(pos + dy) - get_weight(pos - dy)) / h.y,
+        (get_weight(pos + dz) - get_weight(pos - dz)) / h.z,
+    ) * 0.5;
+}
+
+// shades the first crossing of the iso value with Lambert + Blinn-Phong
+fn get_isosurface(ray: Ray) -> RayRecord {
+    let interval = hit_volume(ray);
+    if interval.start >= interval.end {
+        return RayRecord(1.0, vec3f(0.0));
+    }
+
+    let step_size = world_step_size();
+    let iso = uniforms.iso_value;
+    let ns = u32(floor(((interval.end - interval.start) / step_size) + 0.5));
+
+    var t_prev = interval.start;
+    for (var n = 0u; n < ns; n++) {
         let t = interval.start + step_size * (f32(n) + 0.5);
-        let sample_pos = ray_at(ray, t);
-        var sample_weight = 0.0;
-        
-        sample_weight = get_weight(sample_pos);
-        
-        
-
-        if sample_weight > 0.0 {
-            let sample_transparency = exp(-step_size * sample_weight * (sigma));
-            transparency *= sample_transparency;
-
-            //light            
-            let ray_light = create_ray(sample_pos, uniforms.light_dir.xyz);
-            let interval_light = hit_aabb(volume_grid.bbox, ray_light);
-            if interval_light.start < interval_light.end {
-                let ns_light = u32(floor((interval_light.end / step_size) + 0.5));
-
-                var density_light = 0.0;
-
-                for (var nl = 0u; nl < ns_light; nl++) {
-                    let t_light = min(f32(nl) * step_size, interval_light.end);
-                    let sample_pos_light = ray_at(ray_light, t_light);
-                    let sample_weight_light = get_weight(sample_pos_light);
-                    density_light += sample_weight_light;
-                }
-
-                let light_ray_attenutation = exp(-density_light * step_size * sigma);
-                let cos_theta = dot(ray.direction, -uniforms.light_dir) / (length(ray.direction) * length(uniforms.light_dir));
-                result += uniforms.light_col.xyz * light_ray_attenutation * uniforms.scattering * transparency * step_size * sample_weight * phase(cos_theta);
+        counters.samples += 1u;
+        if get_weight(ray_at(ray, t)) < iso {
+            t_prev = t;
+            continue;
+        }
+
+        // the crossing lies in [t_prev, t], refine it by bisection
+        var t_out = t_prev;
+        var t_in = t;
+        for (var i = 0u; i < ISO_BISECTION_STEPS; i++) {
+            let t_mid = 0.5 * (t_out + t_in);
+            if get_weight(ray_at(ray, t_mid)) >= iso {
+                t_in = t_mid;
+            } else {
+                t_out = t_mid;
             }
         }
+
+        counters.samples += ISO_BISECTION_STEPS;
+        counters.early_terminated = true;
+        let hit_pos = ray_at(ray, t_in);
+        let gradient = get_gradient(hit_pos);
+        var normal = -ray.direction.xyz;
+        if length(gradient) > 0.0 {
+            // density grows towards the inside, so the outward normal is the negative gradient
+            normal = -normalize(gradient);
+        }
+
+        let light_dir = normalize(uniforms.light_dir.xyz);
+        let view_dir = -ray.direction.xyz;
+        let half_dir = normalize(light_dir + view_dir);
+
+        let diffuse = max(dot(normal, light_dir), 0.0);
+        let specular = pow(max(dot(normal, half_dir), 0.0), ISO_SHININESS) * select(0.0, 1.0, diffuse > 0.0);
+        let color = ISO_ALBEDO * (ISO_AMBIENT + diffuse * uniforms.light_col.xyz)
+            + specular * uniforms.light_col.xyz;
+
+        return RayRecord(0.0, color);
     }
-    return RayRecord(transparency, result);
+
+    return RayRecord(1.0, vec3f(0.0));
 }
 
-fn phase(cos_theta: f32) -> f32 {
-    let g = uniforms.g;
+fn phase(cos_theta: f32, g: f32) -> f32 {
     let denom = 1.0 + g * g - 2.0 * g * cos_theta;
     return 1.0 / (4.0 / PI) * (1.0 - g * g) / (denom * sqrt(denom));
 }
 
+fn pcg_hash(input: u32) -> u32 {
+    let state = input * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// random number in [0, 1) per pixel, changing with the subpixel offset so that
+// accumulated samples average the noise out
+fn pixel_random(pixel: vec2u) -> f32 {
+    let offset = bitcast<vec2u>(uniforms.pixel_offset);
+    let hash = pcg_hash(pixel.x ^ pcg_hash(pixel.y ^ pcg_hash(offset.x ^ pcg_hash(offset.y))));
+    return f32(hash >> 8u) / 16777216.0;
+}
+
 fn get_ray(u: f32, v: f32) -> Ray {
+    let size = vec2f(textureDimensions(output_texture));
+    let ratio = size.x / size.y;
     return Ray(uniforms.camera_to_world * vec4f(0.0, 0.0, 0.0, 1.0),
         normalize(uniforms.camera_to_world * vec4f((u * 2.0 - 1.0) * ratio, -(v * 2.0 - 1.0), 1.0, 0.0)));
 }
 
+// transmittance towards the light from the center of every light cache texel
+@compute
+@workgroup_size(4, 4, 4)
+fn build_light_cache(@builtin(global_invocation_id) global_id: vec3u) {
+    let size = uniforms.light_cache_size;
+    if any(global_id >= size) {
+        return;
+    }
+
+    let uvw = (vec3f(global_id) + 0.5) / vec3f(size);
+    let pos = mix(uniforms.light_cache_min.xyz, uniforms.light_cache_max.xyz, uvw);
+    let ray_light = create_ray(pos, uniforms.light_dir.xyz);
+    let interval_light = hit_volume(ray_light);
+    var attenuation = 1.0;
+    if interval_light.start < interval_light.end {
+        attenuation = light_attenuation(ray_light, interval_light.end, world_step_size());
+    }
+    let row = (size.x + LIGHT_CACHE_ROW_ALIGNMENT - 1u) / LIGHT_CACHE_ROW_ALIGNMENT * LIGHT_CACHE_ROW_ALIGNMENT;
+    let index = global_id.x + row * (global_id.y + size.y * global_id.z);
+    // rgba16float texels
+    light_cache_output[index] = vec2u(pack2x16float(vec2f(attenuation, 0.0)), pack2x16float(vec2f(0.0, 1.0)));
+}
+
 @compute
 @workgroup_size(16,16)
-fn main(@builtin(global_invocation_id) global_id: vec3u) {
-    let u = f32(global_id.x) / width;
-    let v = f32(global_id.y) / height;
+fn main(@builtin(global_invocation_id) global_id: vec3u, @builtin(local_invocation_index) local_index: u32) {
+    let size = vec2f(textureDimensions(output_texture));
+    let u = (f32(global_id.x) + uniforms.pixel_offset.x) / size.x;
+    let v = (f32(global_id.y) + uniforms.pixel_offset.y) / size.y;
     let ray = get_ray(u, v);
-    let rec = get_color(ray);
-    
-    textureStore(output_texture, global_id.xy, uniforms.color * rec.transparency + vec4f(rec.color, 1.0));
+    var rec = RayRecord(1.0, vec3f(0.0));
+    aovs = Aovs(vec3f(0.0), vec3f(0.0), 0.0, 0.0, 0.0);
+    counters = RayCounters(0u, 0u, false);
+    if uniforms.cap_clip_planes != 0u {
+        rec = get_cap(ray);
+    }
+
+    if rec.transparency > 0.0 {
+        switch uniforms.render_mode {
+            case RENDER_MODE_PHYSICALLY_BASED: {
+                rec = get_color(ray, pixel_random(global_id.xy));
+            }
+            case RENDER_MODE_ISOSURFACE: {
+                rec = get_isosurface(ray);
+            }
+            default: {
+                rec = get_projection(ray);
+            }
+        }
+    }
+
+    var output = vec4f(uniforms.color.xyz * rec.transparency + rec.color, 1.0);
+    if uniforms.transparent_background != 0u {
+        output = vec4f(rec.color, 1.0 - rec.transparency);
+    }
+    textureStore(output_texture, global_id.xy, output);
+    textureStore(lighting_texture, global_id.xy, vec4f(aovs.direct, rec.transparency));
+    textureStore(emission_texture, global_id.xy, vec4f(aovs.emission, aovs.density_integral));
+    textureStore(depth_texture, global_id.xy, vec4f(aovs.first_depth, aovs.weighted_depth, 0.0, 0.0));
+
+    if uniforms.ray_stats != 0u {
+        add_ray_stats(global_id.xy, local_index);
+    }
+}
+
+// rays past the edge of the texture and rays missing the volumes are left out
+fn add_ray_stats(pixel: vec2u, local_index: u32) {
+    if all(pixel < textureDimensions(output_texture)) && counters.samples > 0u {
+        atomicAdd(&group_rays, 1u);
+        atomicAdd(&group_samples, counters.samples);
+        atomicAdd(&group_light_samples, counters.light_samples);
+        if counters.early_terminated {
+            atomicAdd(&group_early_terminated, 1u);
+        }
+    }
+    workgroupBarrier();
+    if local_index != 0u {
+        return;
+    }
+
+    atomicAdd(&ray_stats.rays, atomicLoad(&group_rays));
+    atomicAdd(&ray_stats.early_terminated, atomicLoad(&group_early_terminated));
+    // carries into the high word when the low one wraps around
+    let samples = atomicLoad(&group_samples);
+    if atomicAdd(&ray_stats.samples_low, samples) > 0xffffffffu - samples {
+        atomicAdd(&ray_stats.samples_high, 1u);
+    }
+    let light_samples = atomicLoad(&group_light_samples);
+    if atomicAdd(&ray_stats.light_samples_low, light_samples) > 0xffffffffu - light_samples {
+        atomicAdd(&ray_stats.light_samples_high, 1u);
+    }
 }
@@ -0,0 +1,227 @@
+use crate::editor::settings::Settings;
+use crate::renderer::VolumeRenderer;
+use bytemuck::{Pod, Zeroable};
+use eframe::wgpu::{self, include_wgsl, BufferUsages};
+
+#[repr(C)]
+#[derive(Pod, Zeroable, Clone, Copy)]
+struct ToneMappingUniform {
+    exposure: f32,
+    white_point: f32,
+    operator: u32,
+    encode_srgb: u32,
+    keep_alpha: u32,
+    display_pass: u32,
+    depth_near: f32,
+    depth_far: f32,
+}
+
+fn texture_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    }
+}
+
+/// Draws the HDR result of the volume renderer, or one of its AOVs, into a display target.
+pub struct ToneMapper {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    uniforms_buffer: wgpu::Buffer,
+    /// Targets with an sRGB format encode on write, the shader does it for the others.
+    encode_srgb: bool,
+    /// Writes straight alpha, otherwise transparent pixels are shown over a checkerboard.
+    keep_alpha: bool,
+}
+
+impl ToneMapper {
+    pub fn new(
+        device: &wgpu::Device,
+        renderer: &VolumeRenderer,
+        target_format: wgpu::TextureFormat,
+        keep_alpha: bool,
+    ) -> Self {
+        let blit_module = device.create_shader_module(include_wgsl!("shaders/blit.wgsl"));
+
+        let result_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let uniforms_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tone mapping buffer"),
+            size: std::mem::size_of::<ToneMappingUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let blit_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Blit bind group layout"),
+                entries: &[
+                    texture_layout_entry(0),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    texture_layout_entry(3),
+                    texture_layout_entry(4),
+                    texture_layout_entry(5),
+                ],
+            });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Bind group blit"),
+            layout: &blit_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&renderer.result_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&result_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniforms_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&renderer.aov_texture_views[0]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&renderer.aov_texture_views[1]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&renderer.aov_texture_views[2]),
+                },
+            ],
+        });
+
+        let blit_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Blit pipeline layout"),
+            bind_group_layouts: &[&blit_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Blit pipeline"),
+            layout: Some(&blit_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &blit_module,
+                entry_point: "vs_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &blit_module,
+                entry_point: "fs_main",
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: target_format,
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent::REPLACE,
+                        alpha: wgpu::BlendComponent::REPLACE,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            bind_group,
+            uniforms_buffer,
+            encode_srgb: !target_format.is_srgb(),
+            keep_alpha,
+        }
+    }
+
+    pub fn prepare(&self, queue: &wgpu::Queue, settings: &Settings) {
+        let tone_mapping = &settings.tone_mapping;
+        let bbox = settings.scene_bbox();
+        let camera_distance = (bbox.center() - settings.matrix.w_axis.truncate()).length();
+        let radius = bbox.half_extent().length();
+        let uniforms = ToneMappingUniform {
+            exposure: tone_mapping.exposure.exp2(),
+            white_point: tone_mapping.white_point,
+            operator: tone_mapping.operator.as_u32(),
+            encode_srgb: self.encode_srgb as u32,
+            keep_alpha: self.keep_alpha as u32,
+            display_pass: settings.display_pass.as_u32(),
+            depth_near: (camera_distance - radius).max(0f32),
+            depth_far: camera_distance + radius,
+        };
+        queue.write_buffer(&self.uniforms_buffer, 0, bytemuck::bytes_of(&uniforms));
+    }
+
+    pub fn paint(&self, render_pass: &mut wgpu::RenderPass<'_>) {
+        // one triangle covering the whole target
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Tone maps into a texture, used when there is no window to paint into.
+    /// Blits into `target`, `timestamp_writes` come from a `GpuProfiler`.
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Tone mapping"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes,
+            occlusion_query_set: None,
+        });
+        self.paint(&mut render_pass);
+    }
+}
@@ -1,13 +1,337 @@
 use crate::aabb::Aabb;
+use crate::loader::{LoadError, LoadProgress, LoadStage, ProgressReader};
 use bytemuck::{Pod, Zeroable};
-use vdb_rs::Grid;
+use glam::{IVec3, Mat4, Vec3};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+use std::sync::Arc;
+use vdb_rs::{Grid, GridDescriptor, Map, MetadataValue};
+
+pub const HISTOGRAM_BINS: usize = 64;
+/// Bins used to look up value percentiles.
+const PERCENTILE_BINS: usize = 4096;
+/// Voxels processed between two progress reports.
+const PROGRESS_INTERVAL: usize = 1 << 16;
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
 pub struct VolumeGridStatic {
     size: [u32; 4],
     shift: [i32; 4],
+    /// World space bounds of the grid.
     bbox: Aabb,
+    /// Maps world space positions to dense grid coordinates.
+    world_to_index: [[f32; 4]; 4],
+    /// xyz is the voxel size in world units, w is its smallest component.
+    voxel_size: [f32; 4],
+}
+
+/// Value distribution and footprint of a loaded grid. Counts the voxels the way they are
+/// densified, a tile is one voxel.
+pub struct GridStatistics {
+    pub min_value: f32,
+    pub max_value: f32,
+    pub mean_value: f32,
+    pub active_voxel_count: u64,
+    pub index_bbox: (IVec3, IVec3),
+    pub world_bbox: (Vec3, Vec3),
+    pub dense_bytes: usize,
+    /// Active voxel counts over [min_value, max_value] split into equal bins.
+    pub histogram: [u64; HISTOGRAM_BINS],
+}
+
+impl GridStatistics {
+    pub fn bin_range(&self, bin: usize) -> (f32, f32) {
+        let width = (self.max_value - self.min_value) / HISTOGRAM_BINS as f32;
+        (
+            self.min_value + width * bin as f32,
+            self.min_value + width * (bin + 1) as f32,
+        )
+    }
+}
+
+/// The `class` metadata of a VDB grid.
+#[derive(Clone, Copy, PartialEq)]
+pub enum GridClass {
+    FogVolume,
+    /// Signed distance to a surface, negative inside.
+    LevelSet,
+    Unknown,
+}
+
+impl GridClass {
+    fn from_descriptor(descriptor: &GridDescriptor) -> Self {
+        match descriptor.meta_data.0.get("class") {
+            Some(MetadataValue::String(class)) if class == "level set" => GridClass::LevelSet,
+            Some(MetadataValue::String(class)) if class == "fog volume" => GridClass::FogVolume,
+            _ => GridClass::Unknown,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GridClass::FogVolume => "fog volume",
+            GridClass::LevelSet => "level set",
+            GridClass::Unknown => "unknown",
+        }
+    }
+}
+
+/// CPU copy of the densified grid, laid out the same way as the packed GPU weights.
+pub struct VolumeGridDense {
+    pub size: [usize; 3],
+    pub shift: [i32; 3],
+    pub values: Vec<f32>,
+    pub class: GridClass,
+    pub statistics: GridStatistics,
+}
+
+impl VolumeGridDense {
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Option<f32> {
+        if x >= self.size[0] || y >= self.size[1] || z >= self.size[2] {
+            return None;
+        }
+        let linear_index = z + y * self.size[2] + x * self.size[2] * self.size[1];
+        Some(self.values[linear_index])
+    }
+
+    /// Value below which the given percentage of the positive voxels lie.
+    pub fn percentile(&self, percent: f32) -> f32 {
+        let max = self.statistics.max_value;
+        if max <= 0f32 {
+            return 0f32;
+        }
+
+        let mut bins = vec![0u64; PERCENTILE_BINS];
+        let mut count = 0u64;
+        for &value in &self.values {
+            if value > 0f32 {
+                let bin = (value / max * PERCENTILE_BINS as f32) as usize;
+                bins[bin.min(PERCENTILE_BINS - 1)] += 1;
+                count += 1;
+            }
+        }
+
+        let target = (count as f64 * percent.clamp(0f32, 100f32) as f64 / 100f64).ceil() as u64;
+        let mut cumulative = 0u64;
+        for (bin, &bin_count) in bins.iter().enumerate() {
+            cumulative += bin_count;
+            if cumulative >= target {
+                return (bin + 1) as f32 / PERCENTILE_BINS as f32 * max;
+            }
+        }
+        max
+    }
+
+    /// Converts a dense grid coordinate back to the original VDB index space.
+    pub fn to_index_space(&self, x: usize, y: usize, z: usize) -> [i32; 3] {
+        [
+            x as i32 - self.shift[0],
+            y as i32 - self.shift[1],
+            z as i32 - self.shift[2],
+        ]
+    }
+}
+
+/// How densities are stored in the weights buffer.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Quantization {
+    Linear8,
+    Sqrt8,
+    Half16,
+    Float32,
+}
+
+impl Quantization {
+    pub const ALL: [Quantization; 4] = [
+        Quantization::Linear8,
+        Quantization::Sqrt8,
+        Quantization::Half16,
+        Quantization::Float32,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Quantization::Linear8 => "8-bit linear",
+            Quantization::Sqrt8 => "8-bit sqrt",
+            Quantization::Half16 => "16-bit half",
+            Quantization::Float32 => "32-bit float",
+        }
+    }
+
+    // must match QUANTIZATION_* constants in compute.wgsl
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            Quantization::Linear8 => 0,
+            Quantization::Sqrt8 => 1,
+            Quantization::Half16 => 2,
+            Quantization::Float32 => 3,
+        }
+    }
+
+    pub fn bits(&self) -> u32 {
+        match self {
+            Quantization::Linear8 | Quantization::Sqrt8 => 8,
+            Quantization::Half16 => 16,
+            Quantization::Float32 => 32,
+        }
+    }
+
+    /// Size of `values` densities packed into u32 blocks.
+    pub fn packed_bytes(&self, values: usize) -> usize {
+        let values_per_block = (32 / self.bits()) as usize;
+        values.div_ceil(values_per_block) * std::mem::size_of::<u32>()
+    }
+
+    /// Encodes a normalized density, 8-bit encodings clamp it to [0, 1].
+    fn encode(&self, value: f32) -> u32 {
+        match self {
+            Quantization::Linear8 => (value.clamp(0f32, 1f32) * 255f32).round() as u32,
+            // spends more codes on thin densities
+            Quantization::Sqrt8 => (value.clamp(0f32, 1f32).sqrt() * 255f32).round() as u32,
+            Quantization::Half16 => half::f16::from_f32(value).to_bits() as u32,
+            Quantization::Float32 => value.to_bits(),
+        }
+    }
+
+    fn decode(&self, code: u32) -> f32 {
+        match self {
+            Quantization::Linear8 => code as f32 / 255f32,
+            Quantization::Sqrt8 => (code as f32 / 255f32).powi(2),
+            Quantization::Half16 => half::f16::from_bits(code as u16).to_f32(),
+            Quantization::Float32 => f32::from_bits(code),
+        }
+    }
+}
+
+/// Error a quantization introduces, measured over the non-zero voxels of the dense grid.
+#[derive(Clone, Copy)]
+pub struct QuantizationError {
+    /// Root mean square error of the normalized densities.
+    pub rms: f32,
+    /// Non-zero voxels that are stored as zero.
+    pub lost_voxels: u64,
+}
+
+/// How grid values are mapped to the densities stored on the GPU.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Normalization {
+    /// Divides by the largest value.
+    Max,
+    /// Divides by the value at the given percentile, brighter voxels saturate.
+    Percentile(f32),
+    /// Maps the range to [0, 1], keeps negative values of level sets if `low` is below zero.
+    Range(f32, f32),
+    /// Keeps the values as they are, clamped to [0, 1] by 8-bit storage.
+    Raw,
+}
+
+impl Normalization {
+    pub const ALL: [Normalization; 4] = [
+        Normalization::Max,
+        Normalization::Percentile(99f32),
+        Normalization::Range(0f32, 1f32),
+        Normalization::Raw,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Normalization::Max => "Max",
+            Normalization::Percentile(_) => "Percentile",
+            Normalization::Range(_, _) => "Fixed range",
+            Normalization::Raw => "Raw",
+        }
+    }
+
+    /// Value range mapped to [0, 1], `None` keeps raw values.
+    fn range(&self, dense: &VolumeGridDense) -> Option<(f32, f32)> {
+        match *self {
+            Normalization::Max => Some((0f32, dense.statistics.max_value)),
+            Normalization::Percentile(percent) => Some((0f32, dense.percentile(percent))),
+            Normalization::Range(low, high) => Some((low, high)),
+            Normalization::Raw => None,
+        }
+    }
+}
+
+/// Conversion of level sets to fog, which replaces normalization for them.
+#[derive(Clone, Copy, PartialEq)]
+pub struct LevelSetOptions {
+    /// Scales the packed ramp in the shader, so it works with every storage and changing
+    /// it needs no repack.
+    pub interior_density: f32,
+    /// Width of the density ramp around the surface in voxels.
+    pub falloff: f32,
+}
+
+impl LevelSetOptions {
+    pub fn default() -> Self {
+        Self {
+            interior_density: 1f32,
+            falloff: 1f32,
+        }
+    }
+
+    /// Density ramping from zero outside to one inside, half on the surface.
+    fn density(&self, distance: f32, voxel_size: f32) -> f32 {
+        let width = (self.falloff * voxel_size).max(f32::EPSILON);
+        (0.5f32 - distance / (2f32 * width)).clamp(0f32, 1f32)
+    }
+}
+
+/// Scalar grid of the file rendered as density.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DensitySource {
+    Density,
+    /// Length of the vectors of the velocity grid.
+    VelocityMagnitude,
+}
+
+impl DensitySource {
+    pub const ALL: [DensitySource; 2] = [DensitySource::Density, DensitySource::VelocityMagnitude];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DensitySource::Density => "Density",
+            DensitySource::VelocityMagnitude => "Velocity magnitude",
+        }
+    }
+}
+
+/// Options applied when a grid is packed for the GPU.
+#[derive(Clone, Copy, PartialEq)]
+pub struct LoadOptions {
+    pub normalization: Normalization,
+    pub quantization: Quantization,
+    pub level_set: LevelSetOptions,
+    /// Falls back to the other source when the file has no such grid.
+    pub source: DensitySource,
+}
+
+impl LoadOptions {
+    pub fn default() -> Self {
+        Self {
+            normalization: Normalization::Max,
+            quantization: Quantization::Linear8,
+            level_set: LevelSetOptions::default(),
+            source: DensitySource::Density,
+        }
+    }
+
+    /// Whether grids packed with these options have to be packed again for `other`.
+    pub fn repacks(&self, other: &LoadOptions) -> bool {
+        let level_set = LevelSetOptions {
+            interior_density: self.level_set.interior_density,
+            ..other.level_set
+        };
+        *self
+            != LoadOptions {
+                level_set,
+                ..*other
+            }
+    }
 }
 
 pub struct PackedBoolArray {
@@ -15,39 +339,229 @@ pub struct PackedBoolArray {
 }
 
 impl PackedBoolArray {
-    fn from_array(array: &[half::f16], max: f32) -> Self {
-        let size = array.len() / 8usize;
+    /// Packs as many values into each u32 as the quantization allows, first value in the high bits.
+    fn from_array(
+        array: &[f32],
+        density: impl Fn(f32) -> f32,
+        quantization: Quantization,
+        progress: &LoadProgress,
+    ) -> Result<Self, LoadError> {
+        let bits = quantization.bits();
+        let values_per_block = 32u32 / bits;
+        let size = array.len().div_ceil(values_per_block as usize);
         let mut data = Vec::with_capacity(size);
 
         let mut current_block = 0u32;
         let mut current_block_pos = 0u32;
 
-        for &num in array {
-            if current_block_pos == 4u32 {
+        for (i, &num) in array.iter().enumerate() {
+            if i % PROGRESS_INTERVAL == 0 {
+                progress.report(LoadStage::Packing, i as f32 / array.len() as f32)?;
+            }
+            if current_block_pos == values_per_block {
                 data.push(current_block);
                 current_block = 0u32;
                 current_block_pos = 0u32;
             }
-            let shifted_number = (Self::normalize(num, max) as u32) << (24u32 - current_block_pos*8u32);
-            current_block |= shifted_number;
+            let code = quantization.encode(density(num));
+            current_block |= code << (32u32 - bits * (current_block_pos + 1));
             current_block_pos += 1;
         }
         if current_block_pos > 0u32 {
             data.push(current_block);
         }
 
-        Self { data }
+        Ok(Self { data })
     }
 
-    fn normalize(float_num: half::f16, max: f32) -> u8 {
-        let native_float = float_num.to_f32();
-        let normalized_float = native_float.clamp(0f32, max);
-        (normalized_float*255f32/max).round() as u8
+    fn normalize(value: f32, range: Option<(f32, f32)>) -> f32 {
+        match range {
+            Some((low, high)) if high > low => ((value - low) / (high - low)).clamp(0f32, 1f32),
+            Some(_) => 0f32,
+            None => value,
+        }
+    }
+
+    /// Errors of every quantization in `Quantization::ALL` order.
+    fn quantization_errors(array: &[f32], density: impl Fn(f32) -> f32) -> [QuantizationError; 4] {
+        let mut squared_errors = [0f64; 4];
+        let mut lost_voxels = [0u64; 4];
+        let mut count = 0u64;
+
+        for &num in array {
+            let value = density(num);
+            if value == 0f32 {
+                continue;
+            }
+            count += 1;
+            for (i, quantization) in Quantization::ALL.iter().enumerate() {
+                let decoded = quantization.decode(quantization.encode(value));
+                squared_errors[i] += ((decoded - value) as f64).powi(2);
+                if decoded == 0f32 {
+                    lost_voxels[i] += 1;
+                }
+            }
+        }
+
+        std::array::from_fn(|i| QuantizationError {
+            rms: if count > 0 {
+                (squared_errors[i] / count as f64).sqrt() as f32
+            } else {
+                0f32
+            },
+            lost_voxels: lost_voxels[i],
+        })
+    }
+}
+
+// vdb-rs depends on its own glam version, so its vectors are converted through arrays
+
+/// Index to world transform of the grid. Voxel centers sit on integer index coordinates.
+fn index_to_world(transform: &Map) -> Mat4 {
+    match transform {
+        Map::UniformScaleMap { voxel_size, .. } => {
+            Mat4::from_scale(Vec3::from_array(voxel_size.as_vec3().to_array()))
+        }
+        Map::ScaleTranslateMap {
+            voxel_size,
+            translation,
+            ..
+        } => Mat4::from_scale_rotation_translation(
+            Vec3::from_array(voxel_size.as_vec3().to_array()),
+            glam::Quat::IDENTITY,
+            Vec3::from_array(translation.as_vec3().to_array()),
+        ),
+    }
+}
+
+/// World bounds of the voxels in the inclusive index bbox.
+fn world_bbox(index_to_world: &Mat4, index_bbox: (IVec3, IVec3)) -> (Vec3, Vec3) {
+    let bbox = Aabb::from_min_max(
+        index_bbox.0.as_vec3() - 0.5f32,
+        index_bbox.1.as_vec3() + 0.5f32,
+    )
+    .transformed(index_to_world);
+    (bbox.min(), bbox.max())
+}
+
+/// Gives the voxels outside the narrow band the distance of the band edge, signed by
+/// walking each z column and keeping the sign of the last band voxel. Assumes a closed surface.
+fn fill_level_set(values: &mut [f32], column_length: usize, band: f32) {
+    let inside = -band;
+    let outside = band;
+    for column in values.chunks_mut(column_length) {
+        let mut fill = outside;
+        for value in column {
+            if value.is_nan() {
+                *value = fill;
+            } else {
+                fill = if *value < 0f32 { inside } else { outside };
+            }
+        }
+    }
+}
+
+/// Values written to the dense grid while densifying, summed up as they come.
+struct StatisticsAccumulator {
+    min_value: f32,
+    max_value: f32,
+    sum: f64,
+    values: Vec<f32>,
+}
+
+impl StatisticsAccumulator {
+    fn new() -> Self {
+        Self {
+            min_value: f32::MAX,
+            max_value: f32::MIN,
+            sum: 0f64,
+            values: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, value: f32) {
+        self.min_value = self.min_value.min(value);
+        self.max_value = self.max_value.max(value);
+        self.sum += value as f64;
+        self.values.push(value);
+    }
+
+    fn finish(
+        self,
+        index_bbox: (IVec3, IVec3),
+        world_bbox: (Vec3, Vec3),
+        dense_bytes: usize,
+    ) -> GridStatistics {
+        let active_voxel_count = self.values.len() as u64;
+        let (min_value, max_value) = if active_voxel_count > 0 {
+            (self.min_value, self.max_value)
+        } else {
+            (0f32, 0f32)
+        };
+
+        let mut histogram = [0u64; HISTOGRAM_BINS];
+        let range = max_value - min_value;
+        for value in self.values {
+            let bin = if range > 0f32 {
+                ((value - min_value) / range * HISTOGRAM_BINS as f32) as usize
+            } else {
+                0
+            };
+            histogram[bin.min(HISTOGRAM_BINS - 1)] += 1;
+        }
+
+        GridStatistics {
+            min_value,
+            max_value,
+            mean_value: if active_voxel_count > 0 {
+                (self.sum / active_voxel_count as f64) as f32
+            } else {
+                0f32
+            },
+            active_voxel_count,
+            index_bbox,
+            world_bbox,
+            dense_bytes,
+            histogram,
+        }
     }
 }
 
 impl VolumeGridStatic {
-    pub fn build_from_vdb_grid(vdb_grid: Grid<half::f16>) -> (Self, Vec<u32>) {
+    pub fn bbox(&self) -> Aabb {
+        self.bbox
+    }
+
+    pub fn min_voxel_size(&self) -> f32 {
+        self.voxel_size[3]
+    }
+
+    /// Copy of the grid placed in the scene by an additional object to world transform.
+    pub fn instanced(&self, object_to_world: &Mat4) -> Self {
+        let world_to_index =
+            Mat4::from_cols_array_2d(&self.world_to_index) * object_to_world.inverse();
+        let voxel_size = object_to_world
+            .transform_vector3(Vec3::from_slice(&self.voxel_size[0..3]))
+            .abs();
+        Self {
+            bbox: self.bbox.transformed(object_to_world),
+            world_to_index: world_to_index.to_cols_array_2d(),
+            voxel_size: [
+                voxel_size.x,
+                voxel_size.y,
+                voxel_size.z,
+                voxel_size.min_element(),
+            ],
+            ..*self
+        }
+    }
+
+    /// Densifies the grid, `scalar` turns its values into the ones stored in the dense grid.
+    pub fn build_from_vdb_grid<T: Copy>(
+        vdb_grid: &Grid<T>,
+        scalar: impl Fn(T) -> f32,
+        progress: &LoadProgress,
+    ) -> Result<(Self, VolumeGridDense), LoadError> {
         let min_i = vdb_grid.descriptor.aabb_min().unwrap();
         let max_i = vdb_grid.descriptor.aabb_max().unwrap();
 
@@ -57,38 +571,586 @@ impl VolumeGridStatic {
             length[1] as u32 + 1u32,
             length[2] as u32 + 1u32,
         ];
-        let bbox = Aabb::new(
-            [0f32, 0f32, 0f32, 0f32],
-            [length.x as f32, length.y as f32, length.z as f32, 0f32],
+
+        let shift = [-min_i.x, -min_i.y, -min_i.z];
+        let index_bbox = (
+            IVec3::from_array(min_i.to_array()),
+            IVec3::from_array(max_i.to_array()),
         );
 
-        let shift = [-min_i.x as i32, -min_i.y as i32, -min_i.z as i32];
+        let index_to_world = index_to_world(&vdb_grid.transform);
+        let (world_min, world_max) = world_bbox(&index_to_world, index_bbox);
+        let bbox = Aabb::new(
+            [world_min.x, world_min.y, world_min.z, 0f32],
+            [world_max.x, world_max.y, world_max.z, 0f32],
+        );
+        // dense coordinates are floored in get_weight, so voxel centers move to x.5
+        let world_to_index = Mat4::from_translation(IVec3::from_array(shift).as_vec3() + 0.5f32)
+            * index_to_world.inverse();
+        let voxel_size = index_to_world.transform_vector3(Vec3::ONE).abs();
 
+        let class = GridClass::from_descriptor(&vdb_grid.descriptor);
+        // voxels outside the narrow band of a level set are filled in after densifying
+        let background = match class {
+            GridClass::LevelSet => f32::NAN,
+            _ => 0f32,
+        };
         let mut weights =
             vec![
-                vec![vec![half::f16::default(); length.z as usize + 1usize]; length.y as usize + 1usize];
+                vec![vec![background; length.z as usize + 1usize]; length.y as usize + 1usize];
                 length.x as usize + 1usize
             ];
-        let mut max_weight = 0f32;
- 
-        for (pos, voxel, _level) in vdb_grid.iter() {
-            max_weight = f32::max(max_weight, voxel.to_f32());
+        let mut statistics = StatisticsAccumulator::new();
+        for (i, (pos, voxel, _level)) in vdb_grid.iter().enumerate() {
+            // the tree is walked roughly along x, which is good enough for a progress bar
+            if i % PROGRESS_INTERVAL == 0 {
+                let fraction = (pos.x + shift[0] as f32) / size[0] as f32;
+                progress.report(LoadStage::Densifying, fraction)?;
+            }
+            let value = scalar(voxel);
+            statistics.add(value);
             weights[(pos.x.floor() + shift[0] as f32) as usize]
                 [(pos.y.floor() + shift[1] as f32) as usize]
-                [(pos.z.floor() + shift[2] as f32) as usize] = voxel;
+                [(pos.z.floor() + shift[2] as f32) as usize] = value;
         }
 
+        let mut flattened_weights: Vec<f32> = weights.into_iter().flatten().flatten().collect();
+        let statistics = statistics.finish(
+            index_bbox,
+            (world_min, world_max),
+            std::mem::size_of_val(flattened_weights.as_slice()),
+        );
+        if class == GridClass::LevelSet {
+            let band = statistics.min_value.abs().max(statistics.max_value.abs());
+            fill_level_set(&mut flattened_weights, size[2] as usize, band);
+        }
 
-        let flattened_weights: Vec<half::f16> = weights.into_iter().flatten().flatten().collect();
-        let packed_array = PackedBoolArray::from_array(flattened_weights.as_slice(), max_weight);
-
-        (
+        Ok((
             Self {
                 size: [size[0], size[1], size[2], 0u32],
                 bbox,
                 shift: [shift[0], shift[1], shift[2], 0i32],
+                world_to_index: world_to_index.to_cols_array_2d(),
+                voxel_size: [
+                    voxel_size.x,
+                    voxel_size.y,
+                    voxel_size.z,
+                    voxel_size.min_element(),
+                ],
             },
-            packed_array.data,
+            VolumeGridDense {
+                size: [size[0] as usize, size[1] as usize, size[2] as usize],
+                shift,
+                values: flattened_weights,
+                class,
+                statistics,
+            },
+        ))
+    }
+}
+
+/// CPU copy of a densified vector grid, e.g. the velocities of a simulation.
+pub struct VectorGridDense {
+    pub size: [usize; 3],
+    /// Maps object space positions to dense coordinates with voxel centers at whole numbers.
+    object_to_index: Mat4,
+    index_to_object: Mat4,
+    values: Vec<[half::f16; 3]>,
+    pub max_magnitude: f32,
+    /// Smallest voxel edge in object space.
+    pub voxel_size: f32,
+}
+
+impl VectorGridDense {
+    fn build_from_vdb_grid<T: Copy>(
+        vdb_grid: &Grid<T>,
+        vector: impl Fn(T) -> Vec3,
+        progress: &LoadProgress,
+    ) -> Result<Self, LoadError> {
+        let min_i = vdb_grid.descriptor.aabb_min().unwrap();
+        let max_i = vdb_grid.descriptor.aabb_max().unwrap();
+        let length = max_i - min_i;
+        let size = [
+            length.x as usize + 1usize,
+            length.y as usize + 1usize,
+            length.z as usize + 1usize,
+        ];
+
+        let index_to_object = index_to_world(&vdb_grid.transform)
+            * Mat4::from_translation(Vec3::new(min_i.x as f32, min_i.y as f32, min_i.z as f32));
+        let voxel_size = index_to_object
+            .transform_vector3(Vec3::ONE)
+            .abs()
+            .min_element();
+
+        let mut values = vec![[half::f16::default(); 3]; size[0] * size[1] * size[2]];
+        let mut max_magnitude = 0f32;
+        for (i, (pos, voxel, _level)) in vdb_grid.iter().enumerate() {
+            let x = (pos.x - min_i.x as f32) as usize;
+            if i % PROGRESS_INTERVAL == 0 {
+                progress.report(LoadStage::Densifying, x as f32 / size[0] as f32)?;
+            }
+            let y = (pos.y - min_i.y as f32) as usize;
+            let z = (pos.z - min_i.z as f32) as usize;
+            let value = vector(voxel);
+            max_magnitude = max_magnitude.max(value.length());
+            values[z + y * size[2] + x * size[2] * size[1]] =
+                value.to_array().map(half::f16::from_f32);
+        }
+
+        Ok(Self {
+            size,
+            object_to_index: index_to_object.inverse(),
+            index_to_object,
+            values,
+            max_magnitude,
+            voxel_size,
+        })
+    }
+
+    /// Vector of the voxel at the dense coordinates, in object space units.
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Vec3 {
+        let value = self.values[z + y * self.size[2] + x * self.size[2] * self.size[1]];
+        Vec3::from_array(value.map(half::f16::to_f32))
+    }
+
+    /// Object space position of a voxel center.
+    pub fn voxel_center(&self, x: usize, y: usize, z: usize) -> Vec3 {
+        self.index_to_object
+            .transform_point3(Vec3::new(x as f32, y as f32, z as f32))
+    }
+
+    /// Trilinearly interpolated vector at an object space position, `None` outside the grid.
+    pub fn sample(&self, position: Vec3) -> Option<Vec3> {
+        let index = self.object_to_index.transform_point3(position);
+        let max = Vec3::new(
+            (self.size[0] - 1) as f32,
+            (self.size[1] - 1) as f32,
+            (self.size[2] - 1) as f32,
+        );
+        if index.cmplt(Vec3::ZERO).any() || index.cmpgt(max).any() {
+            return None;
+        }
+
+        let base = index.floor().min(max - 1f32).max(Vec3::ZERO);
+        let t = index - base;
+        let [x, y, z] = base.to_array().map(|c| c as usize);
+        let corner = |dx: usize, dy: usize, dz: usize| {
+            self.get(
+                (x + dx).min(self.size[0] - 1),
+                (y + dy).min(self.size[1] - 1),
+                (z + dz).min(self.size[2] - 1),
+            )
+        };
+        let lerp_x = |dy: usize, dz: usize| corner(0, dy, dz).lerp(corner(1, dy, dz), t.x);
+        let lerp_y = |dz: usize| lerp_x(0, dz).lerp(lerp_x(1, dz), t.y);
+        Some(lerp_y(0).lerp(lerp_y(1), t.z))
+    }
+}
+
+/// Scalar grid of a file that can be packed as density.
+#[derive(Clone)]
+struct ScalarGrid {
+    source: DensitySource,
+    grid: VolumeGridStatic,
+    dense: Arc<VolumeGridDense>,
+}
+
+// grids picked by name before falling back to the first grid of a matching type
+const DENSITY_GRID_NAMES: [&str; 1] = ["density"];
+const VELOCITY_GRID_NAMES: [&str; 3] = ["vel", "v", "velocity"];
+
+/// Name of the grid to load, preferring the given names over the alphabetically first one.
+fn pick_grid(
+    descriptors: &HashMap<String, GridDescriptor>,
+    names: &[&str],
+    grid_type: &str,
+) -> Option<String> {
+    let mut candidates: Vec<&String> = descriptors
+        .iter()
+        .filter(|(_, descriptor)| descriptor.grid_type.starts_with(grid_type))
+        .map(|(name, _)| name)
+        .collect();
+    candidates.sort();
+    names
+        .iter()
+        .find_map(|name| candidates.iter().find(|candidate| candidate == &name))
+        .or(candidates.first())
+        .map(|name| name.to_string())
+}
+
+fn read_grid<T: Pod, R: Read + Seek>(
+    vdb_reader: &mut vdb_rs::VdbReader<R>,
+    name: &str,
+    progress: &LoadProgress,
+) -> Result<Grid<T>, LoadError> {
+    match vdb_reader.read_grid::<T>(name) {
+        Ok(vdb_grid) => Ok(vdb_grid),
+        Err(_) if progress.is_cancelled() => Err(LoadError::Cancelled),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Densifies a velocity grid along with the length of its vectors.
+fn build_vector_grid<T: Copy>(
+    vdb_grid: &Grid<T>,
+    vector: impl Fn(T) -> Vec3,
+    progress: &LoadProgress,
+) -> Result<(VectorGridDense, ScalarGrid), LoadError> {
+    let velocity = VectorGridDense::build_from_vdb_grid(vdb_grid, &vector, progress)?;
+    let (grid, dense) =
+        VolumeGridStatic::build_from_vdb_grid(vdb_grid, |value| vector(value).length(), progress)?;
+    let magnitude = ScalarGrid {
+        source: DensitySource::VelocityMagnitude,
+        grid,
+        dense: Arc::new(dense),
+    };
+    Ok((velocity, magnitude))
+}
+
+/// A loaded grid with everything needed to display and upload it.
+pub struct VolumeData {
+    pub name: String,
+    pub path: String,
+    pub grid: VolumeGridStatic,
+    /// Densities packed with `options`.
+    pub weights: Vec<u32>,
+    pub options: LoadOptions,
+    /// Errors of every quantization in `Quantization::ALL` order.
+    pub quantization_errors: [QuantizationError; 4],
+    /// Shared between repacked copies of the same grid.
+    pub dense: Arc<VolumeGridDense>,
+    /// Grid `dense` comes from, differs from `options.source` when the file lacks that one.
+    pub source: DensitySource,
+    /// Scalar grids of the file, at most one per source.
+    sources: Vec<ScalarGrid>,
+    /// Velocity grid of the file, shown by the render pane overlay.
+    pub velocity: Option<Arc<VectorGridDense>>,
+}
+
+impl VolumeData {
+    /// Reads the density grid of the VDB file and its velocity grid if there is one.
+    pub fn load(path: &str, options: &LoadOptions) -> Result<Self, LoadError> {
+        Self::load_with_progress(path, options, &LoadProgress::none())
+    }
+
+    pub fn load_with_progress(
+        path: &str,
+        options: &LoadOptions,
+        progress: &LoadProgress,
+    ) -> Result<Self, LoadError> {
+        let reader = ProgressReader::new(File::open(path)?, progress)?;
+        let mut vdb_reader = vdb_rs::VdbReader::new(BufReader::new(reader))?;
+        let density_name = pick_grid(
+            &vdb_reader.grid_descriptors,
+            &DENSITY_GRID_NAMES,
+            "Tree_float",
+        );
+        let velocity_name = pick_grid(
+            &vdb_reader.grid_descriptors,
+            &VELOCITY_GRID_NAMES,
+            "Tree_vec3s",
+        );
+        let Some(grid_name) = density_name.clone().or(velocity_name.clone()) else {
+            let available = vdb_reader.available_grids().join(", ");
+            return Err(vdb_rs::ParseError::InvalidGridName(available).into());
+        };
+
+        let mut sources = Vec::new();
+        if let Some(density_name) = density_name {
+            // half grids are converted to f32 while reading, full floats are kept exact
+            let vdb_grid = read_grid::<f32, _>(&mut vdb_reader, &density_name, progress)?;
+            let (grid, dense) =
+                VolumeGridStatic::build_from_vdb_grid(&vdb_grid, |value| value, progress)?;
+            sources.push(ScalarGrid {
+                source: DensitySource::Density,
+                grid,
+                dense: Arc::new(dense),
+            });
+        }
+
+        let mut velocity = None;
+        if let Some(velocity_name) = velocity_name {
+            // half grids are only converted to f32 for scalars, vectors are read as stored
+            let half_float = vdb_reader.grid_descriptors[&velocity_name]
+                .meta_data
+                .is_half_float();
+            let (vectors, magnitude) = if half_float {
+                let vdb_grid =
+                    read_grid::<[half::f16; 3], _>(&mut vdb_reader, &velocity_name, progress)?;
+                build_vector_grid(
+                    &vdb_grid,
+                    |value| Vec3::from_array(value.map(half::f16::to_f32)),
+                    progress,
+                )?
+            } else {
+                let vdb_grid = read_grid::<[f32; 3], _>(&mut vdb_reader, &velocity_name, progress)?;
+                build_vector_grid(&vdb_grid, Vec3::from_array, progress)?
+            };
+            velocity = Some(Arc::new(vectors));
+            sources.push(magnitude);
+        }
+
+        let name = Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or(grid_name);
+
+        Self::pack(name, path.to_string(), sources, velocity, options, progress)
+    }
+
+    fn pack(
+        name: String,
+        path: String,
+        sources: Vec<ScalarGrid>,
+        velocity: Option<Arc<VectorGridDense>>,
+        options: &LoadOptions,
+        progress: &LoadProgress,
+    ) -> Result<Self, LoadError> {
+        let scalar = sources
+            .iter()
+            .find(|scalar| scalar.source == options.source)
+            .unwrap_or(&sources[0])
+            .clone();
+        let dense = scalar.dense;
+        let range = options.normalization.range(&dense);
+        let voxel_size = scalar.grid.min_voxel_size();
+        let density = |value: f32| match dense.class {
+            GridClass::LevelSet => options.level_set.density(value, voxel_size),
+            _ => PackedBoolArray::normalize(value, range),
+        };
+        let packed_array =
+            PackedBoolArray::from_array(&dense.values, density, options.quantization, progress)?;
+        let quantization_errors = PackedBoolArray::quantization_errors(&dense.values, density);
+
+        Ok(Self {
+            name,
+            path,
+            grid: scalar.grid,
+            weights: packed_array.data,
+            options: *options,
+            quantization_errors,
+            dense,
+            source: scalar.source,
+            sources,
+            velocity,
+        })
+    }
+
+    /// Packs the already loaded grids again with different options.
+    pub fn repacked(
+        &self,
+        options: &LoadOptions,
+        progress: &LoadProgress,
+    ) -> Result<Self, LoadError> {
+        Self::pack(
+            self.name.clone(),
+            self.path.clone(),
+            self.sources.clone(),
+            self.velocity.clone(),
+            options,
+            progress,
         )
     }
+
+    pub fn has_source(&self, source: DensitySource) -> bool {
+        self.sources.iter().any(|scalar| scalar.source == source)
+    }
+
+    pub fn packed_bytes(&self) -> usize {
+        std::mem::size_of_val(self.weights.as_slice())
+    }
+
+    /// Size of the weights once repacked with `options`.
+    pub fn packed_bytes_with(&self, options: &LoadOptions) -> usize {
+        let scalar = self
+            .sources
+            .iter()
+            .find(|scalar| scalar.source == options.source)
+            .unwrap_or(&self.sources[0]);
+        options.quantization.packed_bytes(scalar.dense.values.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: [f32; 7] = [0.0, 1.0, 0.5, 0.25, 1.0 / 255.0, 0.001, 0.75];
+
+    fn pack(values: &[f32], quantization: Quantization) -> Vec<u32> {
+        PackedBoolArray::from_array(values, |value| value, quantization, &LoadProgress::none())
+            .unwrap_or_else(|_| unreachable!())
+            .data
+    }
+
+    // must match decode_weight in compute.wgsl
+    fn unpack(data: &[u32], quantization: Quantization, index: usize) -> f32 {
+        let bits = quantization.bits();
+        let values_per_block = (32 / bits) as usize;
+        let block = data[index / values_per_block];
+        let position = (index % values_per_block) as u32;
+        let mask = if bits == 32 {
+            u32::MAX
+        } else {
+            (1u32 << bits) - 1
+        };
+        quantization.decode((block >> (32 - bits * (position + 1))) & mask)
+    }
+
+    // must match remap_density in compute.wgsl
+    fn remap(weight: f32, invert: bool, contrast: f32, gamma: f32) -> f32 {
+        let mut density = if invert { 1.0 - weight } else { weight };
+        if density > 0.0 && density < 1.0 {
+            let low = density.powf(contrast);
+            let high = (1.0 - density).powf(contrast);
+            density = low / (low + high);
+        }
+        density.signum() * density.abs().powf(gamma)
+    }
+
+    #[test]
+    fn inverted_level_set_stays_non_negative() {
+        let level_set = LevelSetOptions {
+            interior_density: 10.0,
+            falloff: 1.0,
+        };
+        for step in -40..=40 {
+            let ramp = level_set.density(step as f32 * 0.1, 1.0);
+            for contrast in [0.1, 1.0, 5.0] {
+                for gamma in [0.2, 1.0, 5.0] {
+                    // the shader scales by the interior density after the remap
+                    let density = remap(ramp, true, contrast, gamma) * level_set.interior_density;
+                    assert!(density >= 0.0, "ramp {} remapped to {}", ramp, density);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn first_value_in_high_bits() {
+        assert_eq!(
+            pack(&[1.0, 0.0, 0.0, 0.0], Quantization::Linear8),
+            [0xff00_0000]
+        );
+        assert_eq!(
+            pack(&[0.0, 0.0, 0.0, 1.0], Quantization::Linear8),
+            [0x0000_00ff]
+        );
+        assert_eq!(pack(&[1.0, 0.0], Quantization::Half16), [0x3c00_0000]);
+        assert_eq!(pack(&[1.0], Quantization::Float32), [1f32.to_bits()]);
+    }
+
+    #[test]
+    fn partial_last_block_is_zero_padded() {
+        let data = pack(&[1.0; 5], Quantization::Linear8);
+        assert_eq!(data, [0xffff_ffff, 0xff00_0000]);
+        assert_eq!(data.len() * 4, Quantization::Linear8.packed_bytes(5));
+        let data = pack(&[1.0; 3], Quantization::Half16);
+        assert_eq!(data, [0x3c00_3c00, 0x3c00_0000]);
+        assert_eq!(data.len() * 4, Quantization::Half16.packed_bytes(3));
+    }
+
+    #[test]
+    fn round_trips_every_quantization() {
+        for quantization in Quantization::ALL {
+            let data = pack(&VALUES, quantization);
+            assert_eq!(data.len() * 4, quantization.packed_bytes(VALUES.len()));
+            for (i, &value) in VALUES.iter().enumerate() {
+                let decoded = unpack(&data, quantization, i);
+                let tolerance = match quantization {
+                    Quantization::Linear8 => 0.5 / 255.0 + f32::EPSILON,
+                    // steps widen towards one
+                    Quantization::Sqrt8 => 1.0 / 255.0 + f32::EPSILON,
+                    // half a unit in the last place of the 11 bit significand
+                    Quantization::Half16 => value * 2f32.powi(-11),
+                    Quantization::Float32 => 0.0,
+                };
+                assert!(
+                    (decoded - value).abs() <= tolerance,
+                    "{} decoded {} as {}",
+                    quantization.label(),
+                    value,
+                    decoded
+                );
+            }
+            assert_eq!(unpack(&data, quantization, 0), 0.0);
+            assert_eq!(unpack(&data, quantization, 1), 1.0);
+        }
+    }
+
+    #[test]
+    fn eight_bit_encodings_clamp() {
+        for quantization in [Quantization::Linear8, Quantization::Sqrt8] {
+            assert_eq!(quantization.decode(quantization.encode(4.0)), 1.0);
+            assert_eq!(quantization.decode(quantization.encode(-1.0)), 0.0);
+        }
+        assert_eq!(
+            Quantization::Half16.decode(Quantization::Half16.encode(4.0)),
+            4.0
+        );
+    }
+
+    #[test]
+    fn error_table_counts_lost_voxels() {
+        let [linear, sqrt, half, float] =
+            PackedBoolArray::quantization_errors(&VALUES, |value| value);
+        // only 0.001 is below half a linear step, sqrt keeps it
+        assert_eq!(linear.lost_voxels, 1);
+        assert_eq!(sqrt.lost_voxels, 0);
+        assert_eq!(half.lost_voxels, 0);
+        assert_eq!(float.lost_voxels, 0);
+        // 1/255 and 0.001 aren't exact in half floats
+        assert!(half.rms > 0.0);
+        assert_eq!(float.rms, 0.0);
+    }
+
+    #[test]
+    fn float_keeps_values_half_floats_cannot_hold() {
+        // raw densities past the largest half float
+        let values = [1e5, 70000.0, 1.0 + 1e-4];
+        let float = pack(&values, Quantization::Float32);
+        let half = pack(&values, Quantization::Half16);
+        for (i, &value) in values.iter().enumerate() {
+            assert_eq!(unpack(&float, Quantization::Float32, i), value);
+            assert_ne!(unpack(&half, Quantization::Half16, i), value);
+        }
+        let [_, _, half, float] = PackedBoolArray::quantization_errors(&values, |value| value);
+        assert!(half.rms > 0.0);
+        assert_eq!(float.rms, 0.0);
+    }
+
+    #[test]
+    fn sqrt_has_less_error_on_thin_densities() {
+        let values = [0.001, 0.002, 0.004, 0.01];
+        let [linear, sqrt, _, _] = PackedBoolArray::quantization_errors(&values, |value| value);
+        assert!(sqrt.rms < linear.rms);
+        assert_eq!(linear.lost_voxels, 1);
+        assert_eq!(sqrt.lost_voxels, 0);
+    }
+
+    #[test]
+    fn statistics_count_every_added_value_once() {
+        let mut accumulator = StatisticsAccumulator::new();
+        for value in [0.0, 1.0, 0.5, 0.5] {
+            accumulator.add(value);
+        }
+        let bbox = (IVec3::ZERO, IVec3::ONE);
+        let statistics = accumulator.finish(bbox, (Vec3::ZERO, Vec3::ONE), 16);
+        assert_eq!(statistics.active_voxel_count, 4);
+        assert_eq!((statistics.min_value, statistics.max_value), (0.0, 1.0));
+        assert_eq!(statistics.mean_value, 0.5);
+        assert_eq!(statistics.histogram.iter().sum::<u64>(), 4);
+        assert_eq!(statistics.histogram[0], 1);
+        assert_eq!(statistics.histogram[HISTOGRAM_BINS / 2], 2);
+        assert_eq!(statistics.histogram[HISTOGRAM_BINS - 1], 1);
+
+        let empty = StatisticsAccumulator::new().finish(bbox, (Vec3::ZERO, Vec3::ONE), 0);
+        assert_eq!(empty.active_voxel_count, 0);
+        assert_eq!(
+            (empty.min_value, empty.max_value, empty.mean_value),
+            (0.0, 0.0, 0.0)
+        );
+        assert_eq!(empty.histogram.iter().sum::<u64>(), 0);
+    }
 }